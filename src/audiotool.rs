@@ -1,17 +1,295 @@
-use std::{collections::HashMap, sync::RwLock};
+use std::{
+    collections::HashMap,
+    env, fmt,
+    sync::{Mutex, RwLock},
+    time::{Duration, Instant},
+};
 
 use json::JsonValue;
-use reqwest::{header::HeaderMap, Client};
+use reqwest::{header::HeaderMap, Client, Request, Response, StatusCode};
 
-const KEEP_ALIVE_URL: &str = "https://www.audiotool.com/";
+const DEFAULT_WEB_URL: &str = "https://www.audiotool.com";
+const DEFAULT_API_URL: &str = "https://api.audiotool.com";
+
+/// environment variable to override the base URL of the Audiotool website
+pub const ENV_WEB_URL: &str = "AUDIOTOOL_WEB_URL";
+/// environment variable to override the base URL of the Audiotool API
+pub const ENV_API_URL: &str = "AUDIOTOOL_API_URL";
+/// environment variable holding the name of the Audiotool account to log in with (optional)
+pub const ENV_USERNAME: &str = "AUDIOTOOL_USERNAME";
+/// environment variable holding the password of the Audiotool account to log in with
+pub const ENV_PASSWORD: &str = "AUDIOTOOL_PASSWORD";
+
+const SESSION_COOKIE: &str = "cular-session";
+
+const DEFAULT_OPEN_DURATION_SECS: u64 = 60;
+const DEFAULT_KEEP_ALIVE_INTERVAL_SECS: u64 = 60;
+
+#[derive(Debug)]
+pub enum RequestError {
+    /// Audiotool refused to serve the resource with the current session
+    Unauthorized(String),
+    Failed(String),
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::Unauthorized(message) | RequestError::Failed(message) => message.fmt(f),
+        }
+    }
+}
+
+/// Account used to access tracks which aren't public.
+struct Credentials {
+    username: String,
+    password: String,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // never leak the password into logs
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum AuthState {
+    /// no credentials configured; only public tracks are available
+    Anonymous,
+    /// credentials are configured, but the bot hasn't logged in yet
+    LoggedOut,
+    LoggedIn(Instant),
+    /// Audiotool rejected the session; the next keep-alive will log in again
+    Expired,
+    /// the most recent login attempt failed; the next keep-alive will try again
+    Failed(String),
+    /// Audiotool rejected the credentials; there won't be another attempt until the bot is
+    /// restarted with other credentials
+    Rejected(String),
+}
+
+/// Rules that apply to every request sent to Audiotool.
+#[derive(Clone, Copy, Debug)]
+pub struct RequestPolicy {
+    /// upper limit for a single attempt (connect + response)
+    pub timeout: Duration,
+    /// number of additional attempts after a transient failure
+    pub max_retries: u32,
+    /// delay before the first retry; doubles with every further retry
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// number of consecutive failed requests which will open the circuit
+    pub failure_threshold: u32,
+    /// how long requests will be rejected right away once the circuit is open
+    pub open_duration: Duration,
+    /// regular delay between two keep-alive requests
+    pub keep_alive_interval: Duration,
+}
+
+impl Default for RequestPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(8),
+            failure_threshold: 5,
+            open_duration: Duration::from_secs(DEFAULT_OPEN_DURATION_SECS),
+            keep_alive_interval: Duration::from_secs(DEFAULT_KEEP_ALIVE_INTERVAL_SECS),
+        }
+    }
+}
+
+impl RequestPolicy {
+    fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(1 << retry.min(16))
+            .min(self.max_backoff)
+    }
+}
+
+#[derive(Default)]
+struct CircuitBreaker {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    /// whether the request which probes a half-open circuit is still in flight
+    probing: bool,
+    last_error: Option<String>,
+    last_success: Option<Instant>,
+    total_requests: u64,
+    total_failures: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    /// requests pass normally
+    Closed,
+    /// requests are rejected without contacting the server
+    Open(Duration),
+    /// the next request is a probe which decides whether to close the circuit again; other
+    /// requests are rejected while the probe is in flight
+    HalfOpen,
+}
+
+/// Lets the next request probe the half-open circuit once the current probe has ended; even if
+/// it has been cancelled.
+struct Probe<'a>(&'a Mutex<CircuitBreaker>);
+
+impl Drop for Probe<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut circuit) = self.0.lock() {
+            circuit.probing = false;
+        }
+    }
+}
+
+/// Snapshot of the request policy's state; used for the `status` command.
+pub struct RequestStatus {
+    pub policy: RequestPolicy,
+    pub username: Option<String>,
+    pub auth: AuthState,
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    pub last_success: Option<Duration>,
+    pub total_requests: u64,
+    pub total_failures: u64,
+}
+
+impl fmt::Display for RequestStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.state {
+            CircuitState::Closed => writeln!(f, "Audiotool connection: **ok**")?,
+            CircuitState::Open(remaining) => writeln!(
+                f,
+                "Audiotool connection: **suspended** after {} failed request(s); retrying in {}s",
+                self.consecutive_failures,
+                remaining.as_secs() + 1
+            )?,
+            CircuitState::HalfOpen => writeln!(
+                f,
+                "Audiotool connection: **recovering** after {} failed request(s)",
+                self.consecutive_failures
+            )?,
+        }
+        match self.last_success {
+            Some(elapsed) => writeln!(f, "Last successful request: {}s ago", elapsed.as_secs())?,
+            None => writeln!(f, "Last successful request: never")?,
+        }
+        if let Some(last_error) = &self.last_error {
+            writeln!(f, "Last error: `{last_error}`")?;
+        }
+        let username = self.username.as_deref().unwrap_or_default();
+        match &self.auth {
+            AuthState::Anonymous => writeln!(f, "Audiotool login: none (public tracks only)")?,
+            AuthState::LoggedOut => {
+                writeln!(f, "Audiotool login: `{username}` (not logged in yet)")?;
+            }
+            AuthState::LoggedIn(since) => writeln!(
+                f,
+                "Audiotool login: `{username}` (logged in {}s ago)",
+                since.elapsed().as_secs()
+            )?,
+            AuthState::Expired => {
+                writeln!(
+                    f,
+                    "Audiotool login: `{username}` (**expired**; renewing shortly)"
+                )?;
+            }
+            AuthState::Failed(err) => {
+                writeln!(f, "Audiotool login: `{username}` (**failed**: {err})")?;
+            }
+            AuthState::Rejected(err) => {
+                writeln!(
+                    f,
+                    "Audiotool login: `{username}` (**rejected**: {err}; fix the credentials and restart the bot)"
+                )?;
+            }
+        }
+        write!(
+            f,
+            "Requests: {} ({} failed); timeout {}s, up to {} retries, circuit opens after {} failures for {}s",
+            self.total_requests,
+            self.total_failures,
+            self.policy.timeout.as_secs(),
+            self.policy.max_retries,
+            self.policy.failure_threshold,
+            self.policy.open_duration.as_secs()
+        )
+    }
+}
 
 pub struct AudiotoolHttpClient {
     client: RwLock<Client>,
     cookies: RwLock<HashMap<String, String>>,
+    policy: RequestPolicy,
+    circuit: Mutex<CircuitBreaker>,
+    web_url: String,
+    api_url: String,
+    credentials: Option<Credentials>,
+    auth: Mutex<AuthState>,
 }
 
 impl AudiotoolHttpClient {
+    /// Creates a client whose base URLs can be overridden by environment variables.
+    ///
+    /// This allows to point the bot at a local stand-in for the Audiotool servers.
+    pub fn from_env() -> Self {
+        let mut client = Self::default();
+        if let Ok(web_url) = env::var(ENV_WEB_URL) {
+            web_url
+                .trim_end_matches('/')
+                .clone_into(&mut client.web_url);
+        }
+        if let Ok(api_url) = env::var(ENV_API_URL) {
+            api_url
+                .trim_end_matches('/')
+                .clone_into(&mut client.api_url);
+        }
+        if let (Ok(username), Ok(password)) = (env::var(ENV_USERNAME), env::var(ENV_PASSWORD)) {
+            println!("using audiotool account {username}");
+            client.credentials = Some(Credentials { username, password });
+            *client.auth.get_mut().expect("failed to unlock auth state") = AuthState::LoggedOut;
+        }
+        println!(
+            "using audiotool web URL {} and API URL {}",
+            client.web_url, client.api_url
+        );
+        client
+    }
+
+    /// base URL of the website (without trailing slash); e.g. `https://www.audiotool.com`
+    pub fn web_url(&self) -> &str {
+        &self.web_url
+    }
+
+    /// base URL of the API (without trailing slash); e.g. `https://api.audiotool.com`
+    pub fn api_url(&self) -> &str {
+        &self.api_url
+    }
+
+    /// Regex pattern which matches the website's base URL regardless of the scheme being used.
+    pub fn web_url_pattern(&self) -> String {
+        let host = self
+            .web_url
+            .strip_prefix("https://")
+            .or_else(|| self.web_url.strip_prefix("http://"))
+            .unwrap_or(&self.web_url);
+        format!("http[s]?://{}", regex::escape(host))
+    }
+
+    /// Refreshes the session; logs in first if credentials are configured and there's no valid
+    /// session yet.
     pub async fn keep_alive(&self) {
+        if self.needs_login() {
+            if let Err(err) = self.login().await {
+                eprintln!("audiotool login failed: {err}");
+            }
+        }
+
         let request = {
             let cookies = self.cookies.read().expect("failed to unlock cular_cookie");
             let mut cookie_string = String::new();
@@ -25,15 +303,14 @@ impl AudiotoolHttpClient {
             }
 
             let client = self.client.read().expect("failed to unlock http client");
-            let request = client
-                .get(KEEP_ALIVE_URL)
+            // println!("keep alive-request: {request:#?}");
+            client
+                .get(format!("{}/", self.web_url))
                 //.header("Cookie", cookie_string)
                 .build()
-                .expect("failed to build keep alive request");
-            // println!("keep alive-request: {request:#?}");
-            client.execute(request)
+                .expect("failed to build keep alive request")
         };
-        match request.await {
+        match self.execute(request).await {
             Ok(response) => {
                 // println!("keep alive-response: {response:#?}");
                 match Self::extract_cookies(response.headers()) {
@@ -61,6 +338,119 @@ impl AudiotoolHttpClient {
         }
     }
 
+    fn needs_login(&self) -> bool {
+        matches!(
+            *self.auth.lock().expect("failed to unlock auth state"),
+            AuthState::LoggedOut | AuthState::Expired | AuthState::Failed(_)
+        )
+    }
+
+    fn set_auth_state(&self, state: AuthState) {
+        *self.auth.lock().expect("failed to unlock auth state") = state;
+    }
+
+    /// Logs in with the configured credentials; does nothing if there are none.
+    async fn login(&self) -> Result<(), RequestError> {
+        let Some(credentials) = &self.credentials else {
+            return Ok(());
+        };
+
+        let request = {
+            let client = self.client.read().expect("failed to unlock http client");
+            client
+                .post(format!("{}/login", self.web_url))
+                .form(&[
+                    ("username", credentials.username.as_str()),
+                    ("password", credentials.password.as_str()),
+                ])
+                .build()
+                .map_err(|err| {
+                    RequestError::Failed(format!("failed to build login request: {err}"))
+                })?
+        };
+
+        let result = match self.execute(request).await {
+            Ok(response) => match Self::extract_cookies(response.headers()) {
+                Ok(cookies) if cookies.contains_key(SESSION_COOKIE) => {
+                    self.cookies
+                        .write()
+                        .expect("failed to unlock cular_cookie")
+                        .extend(cookies);
+                    Ok(())
+                }
+                Ok(_) => Err(RequestError::Failed(
+                    "login didn't yield a session".to_owned(),
+                )),
+                Err(err) => Err(RequestError::Failed(err)),
+            },
+            Err(RequestError::Unauthorized(_)) => Err(RequestError::Unauthorized(format!(
+                "Audiotool rejected the credentials of `{}`",
+                credentials.username
+            ))),
+            Err(err) => Err(err),
+        };
+
+        match &result {
+            Ok(()) => {
+                println!("logged in to audiotool as {}", credentials.username);
+                self.set_auth_state(AuthState::LoggedIn(Instant::now()));
+            }
+            // trying the same credentials again would only get the account locked
+            Err(RequestError::Unauthorized(err)) => {
+                self.set_auth_state(AuthState::Rejected(err.clone()));
+            }
+            Err(RequestError::Failed(err)) => self.set_auth_state(AuthState::Failed(err.clone())),
+        }
+        result
+    }
+
+    /// Turns a 401/403 response into an error message which tells the user how to get access.
+    ///
+    /// Only a 401 means that the session has expired; a 403 usually means that the account isn't
+    /// allowed to access this particular resource. Only the website's root is available to every
+    /// session, so a 403 there means that the session has expired as well.
+    fn unauthorized(&self, url: &reqwest::Url, status: StatusCode) -> RequestError {
+        if self.credentials.is_none() {
+            return RequestError::Unauthorized(format!(
+                "Audiotool denied access to {url}; private tracks require the bot to log in (see `{ENV_USERNAME}` and `{ENV_PASSWORD}`)"
+            ));
+        }
+
+        let is_session_endpoint = url.as_str().trim_end_matches('/') == self.web_url;
+        let mut auth = self.auth.lock().expect("failed to unlock auth state");
+        match *auth {
+            AuthState::LoggedIn(_) if status == StatusCode::UNAUTHORIZED || is_session_endpoint => {
+                eprintln!("audiotool session expired");
+                *auth = AuthState::Expired;
+            }
+            AuthState::Rejected(_) => {
+                return RequestError::Unauthorized(format!(
+                    "Audiotool denied access to {url}; the bot couldn't log in as Audiotool rejected its credentials"
+                ));
+            }
+            _ => {}
+        }
+        if let AuthState::Expired = *auth {
+            RequestError::Unauthorized(format!(
+                "Audiotool denied access to {url}; the bot's login expired and will be renewed within a minute"
+            ))
+        } else {
+            RequestError::Unauthorized(format!(
+                "Audiotool denied access to {url}; the bot's account isn't allowed to access this track"
+            ))
+        }
+    }
+
+    /// Time to wait until the next keep-alive request should be sent.
+    ///
+    /// While the circuit is open there's no point in firing before it will allow requests again.
+    pub fn keep_alive_delay(&self) -> Duration {
+        match self.circuit_state() {
+            CircuitState::Open(remaining) => remaining.max(self.policy.keep_alive_interval),
+            CircuitState::Closed | CircuitState::HalfOpen => self.policy.keep_alive_interval,
+        }
+    }
+
     pub fn cular_cookie(&self) -> HashMap<String, String> {
         self.cookies
             .write()
@@ -68,6 +458,181 @@ impl AudiotoolHttpClient {
             .clone()
     }
 
+    pub fn status(&self) -> RequestStatus {
+        let state = self.circuit_state();
+        let circuit = self
+            .circuit
+            .lock()
+            .expect("failed to unlock circuit breaker");
+        RequestStatus {
+            policy: self.policy,
+            username: self
+                .credentials
+                .as_ref()
+                .map(|credentials| credentials.username.clone()),
+            auth: self
+                .auth
+                .lock()
+                .expect("failed to unlock auth state")
+                .clone(),
+            state,
+            consecutive_failures: circuit.consecutive_failures,
+            last_error: circuit.last_error.clone(),
+            last_success: circuit.last_success.map(|instant| instant.elapsed()),
+            total_requests: circuit.total_requests,
+            total_failures: circuit.total_failures,
+        }
+    }
+
+    fn circuit_state(&self) -> CircuitState {
+        let circuit = self
+            .circuit
+            .lock()
+            .expect("failed to unlock circuit breaker");
+        match circuit.open_until {
+            Some(open_until) => {
+                let now = Instant::now();
+                if now < open_until {
+                    CircuitState::Open(open_until - now)
+                } else {
+                    CircuitState::HalfOpen
+                }
+            }
+            None => CircuitState::Closed,
+        }
+    }
+
+    /// Rejects the request while the circuit is open or while another request probes the
+    /// half-open circuit; otherwise the request may pass, as the probe if the circuit is half-open.
+    fn admit(&self) -> Result<Option<Probe<'_>>, RequestError> {
+        let mut circuit = self
+            .circuit
+            .lock()
+            .expect("failed to unlock circuit breaker");
+        let Some(open_until) = circuit.open_until else {
+            return Ok(None);
+        };
+        let now = Instant::now();
+        if now < open_until {
+            return Err(RequestError::Failed(format!(
+                "Audiotool requests are suspended for another {}s due to repeated failures",
+                (open_until - now).as_secs() + 1
+            )));
+        }
+        if circuit.probing {
+            return Err(RequestError::Failed(
+                "Audiotool requests are suspended until it responds again".to_owned(),
+            ));
+        }
+        circuit.probing = true;
+        Ok(Some(Probe(&self.circuit)))
+    }
+
+    fn record_success(&self) {
+        let mut circuit = self
+            .circuit
+            .lock()
+            .expect("failed to unlock circuit breaker");
+        circuit.total_requests += 1;
+        circuit.consecutive_failures = 0;
+        circuit.open_until = None;
+        circuit.last_success = Some(Instant::now());
+    }
+
+    /// A client error (4xx) tells nothing about whether Audiotool is healthy, so it neither
+    /// counts towards opening the circuit nor closes it.
+    fn record_rejection(&self, err: &str) {
+        let mut circuit = self
+            .circuit
+            .lock()
+            .expect("failed to unlock circuit breaker");
+        circuit.total_requests += 1;
+        circuit.total_failures += 1;
+        circuit.last_error = Some(err.to_owned());
+    }
+
+    fn record_failure(&self, err: &str) {
+        let mut circuit = self
+            .circuit
+            .lock()
+            .expect("failed to unlock circuit breaker");
+        circuit.total_requests += 1;
+        circuit.total_failures += 1;
+        circuit.consecutive_failures += 1;
+        circuit.last_error = Some(err.to_owned());
+        if circuit.consecutive_failures >= self.policy.failure_threshold {
+            if circuit.open_until.is_none() {
+                eprintln!(
+                    "audiotool requests suspended after {} consecutive failures",
+                    circuit.consecutive_failures
+                );
+            }
+            circuit.open_until = Some(Instant::now() + self.policy.open_duration);
+        }
+    }
+
+    /// Sends a request according to the `RequestPolicy`.
+    ///
+    /// Connection errors, timeouts and server errors (5xx) are retried with an exponential
+    /// backoff. Other error responses are returned right away as they won't get any better.
+    async fn execute(&self, mut request: Request) -> Result<Response, RequestError> {
+        let _probe = self.admit()?;
+
+        *request.timeout_mut() = Some(self.policy.timeout);
+        let client = self
+            .client
+            .read()
+            .expect("failed to unlock http client")
+            .clone();
+
+        let mut retry = 0;
+        loop {
+            let attempt = request.try_clone().ok_or_else(|| {
+                RequestError::Failed(format!("request to {} cannot be repeated", request.url()))
+            })?;
+
+            let err = match client.execute(attempt).await {
+                Ok(response) if is_transient_status(response.status()) => {
+                    format!("{} responded with {}", request.url(), response.status())
+                }
+                Ok(response) if response.status().is_success() => {
+                    self.record_success();
+                    return Ok(response);
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    self.record_rejection(&format!("{} responded with {status}", request.url()));
+                    return if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN
+                    {
+                        Err(self.unauthorized(request.url(), status))
+                    } else {
+                        Err(RequestError::Failed(format!(
+                            "{} responded with {status}",
+                            request.url()
+                        )))
+                    };
+                }
+                Err(err) if err.is_timeout() || err.is_connect() || err.is_request() => {
+                    err.to_string()
+                }
+                Err(err) => {
+                    let err = err.to_string();
+                    self.record_failure(&err);
+                    return Err(RequestError::Failed(err));
+                }
+            };
+
+            if retry >= self.policy.max_retries {
+                self.record_failure(&err);
+                return Err(RequestError::Failed(err));
+            }
+            let backoff = self.policy.backoff(retry);
+            eprintln!("{err}; retrying in {}ms", backoff.as_millis());
+            tokio::time::sleep(backoff).await;
+            retry += 1;
+        }
+    }
+
     fn extract_cookies(headers: &HeaderMap) -> Result<HashMap<String, String>, String> {
         let mut cookies = HashMap::new();
         for cookie in headers.get_all("set-cookie") {
@@ -89,89 +654,95 @@ impl AudiotoolHttpClient {
         Ok(cookies)
     }
 
-    pub async fn request_track_details(&self, track_key: &str) -> Result<JsonValue, String> {
-        let track_details_response = {
+    async fn request_json(&self, url: &str, subject: &str) -> Result<JsonValue, RequestError> {
+        let request = {
             let client = self.client.read().expect("failed to unlock http client");
-            let track_details_url =
-                format!("https://www.audiotool.com/track/{track_key}/details.json");
-            let track_details_request = client.get(track_details_url).build().unwrap();
-            client.execute(track_details_request)
+            client.get(url).build().map_err(|err| {
+                RequestError::Failed(format!("failed to build {subject} request: {err}"))
+            })?
         };
 
-        let track_details_str = track_details_response
+        let details_str = self
+            .execute(request)
             .await
-            .expect("track details request failed")
+            .map_err(|err| match err {
+                RequestError::Unauthorized(message) => RequestError::Unauthorized(message),
+                RequestError::Failed(message) => {
+                    RequestError::Failed(format!("{subject} request failed: {message}"))
+                }
+            })?
             .text()
             .await
-            .unwrap();
+            .map_err(|err| RequestError::Failed(format!("failed to read {subject}: {err}")))?;
 
-        json::parse(&track_details_str)
-            .map_err(|err| format!("failed to parse track details as json: {err}"))
+        json::parse(&details_str).map_err(|err| {
+            RequestError::Failed(format!("failed to parse {subject} as json: {err}"))
+        })
     }
 
-    pub async fn request_single_charts_details(&self) -> Result<JsonValue, String> {
-        let response = {
-            let client = self.client.read().expect("failed to unlock http client");
-            let url = "https://api.audiotool.com/tracks/charts.json?offset=0&limit=10";
-            let request = client.get(url).build().unwrap();
-            client.execute(request)
-        };
+    pub async fn request_track_details(&self, track_key: &str) -> Result<JsonValue, RequestError> {
+        let url = format!("{}/track/{track_key}/details.json", self.web_url);
+        self.request_json(&url, "track details").await
+    }
 
-        let details_str = response
-            .await
-            .expect("single charts details request failed")
-            .text()
-            .await
-            .unwrap();
+    /// most recent comments first
+    pub async fn request_track_comments(
+        &self,
+        track_key: &str,
+        count: usize,
+    ) -> Result<JsonValue, RequestError> {
+        let url = format!(
+            "{}/track/{track_key}/comments.json?offset=0&limit={count}",
+            self.api_url
+        );
+        self.request_json(&url, "track comments").await
+    }
 
-        json::parse(&details_str)
-            .map_err(|err| format!("failed to parse single charts details as json: {err}"))
+    pub async fn request_single_charts_details(&self) -> Result<JsonValue, RequestError> {
+        let url = format!("{}/tracks/charts.json?offset=0&limit=10", self.api_url);
+        self.request_json(&url, "single charts details").await
     }
 
     pub async fn request_genre_charts_details(
         &self,
         genre_key: &str,
         date: &str,
-    ) -> Result<JsonValue, String> {
-        let response = {
-            let client = self.client.read().expect("failed to unlock http client");
-            let url = format!(
-                "https://api.audiotool.com/genre/{genre_key}/charts/{date}.json?offset=0&limit=10",
-            );
-            let request = client.get(url).build().unwrap();
-            client.execute(request)
-        };
-
-        let details_str = response
-            .await
-            .expect("single charts details request failed")
-            .text()
-            .await
-            .unwrap();
+    ) -> Result<JsonValue, RequestError> {
+        let url = format!(
+            "{}/genre/{genre_key}/charts/{date}.json?offset=0&limit=10",
+            self.api_url
+        );
+        self.request_json(&url, "genre charts details").await
+    }
 
-        json::parse(&details_str)
-            .map_err(|err| format!("failed to parse single charts details as json: {err}"))
+    pub async fn request_album_details(&self, album_key: &str) -> Result<JsonValue, RequestError> {
+        let url = format!(
+            "{}/album/{album_key}/tracks.json?offset=0&limit=100",
+            self.api_url
+        );
+        self.request_json(&url, "album details").await
     }
+}
 
-    pub async fn request_album_details(&self, album_key: &str) -> Result<JsonValue, String> {
-        let response = {
-            let client = self.client.read().expect("failed to unlock http client");
-            let url = format!(
-                "https://api.audiotool.com/album/{album_key}/tracks.json?offset=0&limit=100"
-            );
-            let request = client.get(url).build().unwrap();
-            client.execute(request)
-        };
+fn is_transient_status(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
 
-        let details_str = response
-            .await
-            .expect("single charts details request failed")
-            .text()
-            .await
-            .unwrap();
+#[cfg(test)]
+impl AudiotoolHttpClient {
+    pub fn with_policy(self, policy: RequestPolicy) -> Self {
+        Self { policy, ..self }
+    }
 
-        json::parse(&details_str)
-            .map_err(|err| format!("failed to parse single charts details as json: {err}"))
+    pub fn with_credentials(self, username: &str, password: &str) -> Self {
+        Self {
+            credentials: Some(Credentials {
+                username: username.to_owned(),
+                password: password.to_owned(),
+            }),
+            auth: Mutex::new(AuthState::LoggedOut),
+            ..self
+        }
     }
 }
 
@@ -186,6 +757,12 @@ impl Default for AudiotoolHttpClient {
         Self {
             client: RwLock::new(client),
             cookies: RwLock::new(HashMap::new()),
+            policy: RequestPolicy::default(),
+            circuit: Mutex::default(),
+            web_url: DEFAULT_WEB_URL.to_owned(),
+            api_url: DEFAULT_API_URL.to_owned(),
+            credentials: None,
+            auth: Mutex::new(AuthState::Anonymous),
         }
     }
 }
@@ -3,6 +3,7 @@ use songbird::{error::JoinError, tracks::TrackError};
 use crate::{
     help::HelpTopic,
     message::MessageChannel,
+    stats::{StatsCategory, StatsPeriod},
     track::selection::{TrackIndex, TrackIndexRange, TrackIndexSelection},
 };
 use std::{fmt, time::Duration};
@@ -11,6 +12,8 @@ pub const CMD_HELP: &str = "help";
 pub const CMD_JOIN: &str = "join";
 pub const CMD_LEAVE: &str = "leave";
 pub const CMD_ENQUEUE: &str = "+";
+pub const CMD_ENQUEUE_NEXT: &str = "+next";
+pub const CMD_ENQUEUE_AT: &str = "+at";
 pub const CMD_PAUSE: &str = "pause";
 pub const CMD_RESUME: &str = "resume";
 pub const CMD_STOP: &str = "stop";
@@ -21,17 +24,29 @@ pub const CMD_NEXT: &str = "next";
 pub const CMD_PREV: &str = "prev";
 pub const CMD_REMOVE: &str = "-";
 pub const CMD_SEEK: &str = "seek";
+pub const CMD_LOOP: &str = "loop";
 pub const CMD_NOW: &str = "now";
+pub const CMD_INFO: &str = "info";
 pub const CMD_REVERSE: &str = "reverse";
 pub const CMD_QUOTA: &str = "quota";
 pub const CMD_MOVE: &str = "move";
 pub const CMD_WHEN: &str = "when";
+pub const CMD_STATUS: &str = "status";
+pub const CMD_UNDO: &str = "undo";
+pub const CMD_REDO: &str = "redo";
+pub const CMD_HISTORY: &str = "history";
+pub const CMD_STATS: &str = "stats";
+pub const CMD_PLAYLIST: &str = "playlist";
+pub const CMD_SHARED_PLAYLIST: &str = "shared";
 
 pub enum Command {
     Help(HelpTopic),
-    Join,
+    /// an explicit voice channel (mention, id or name); the issuer's channel otherwise
+    Join(Option<String>),
     Leave,
     Enqueue(Vec<(String, Option<String>)>),
+    /// inserts the tracks at the given position instead of appending them
+    Insert(TrackIndex, Vec<(String, Option<String>)>),
     Pause,
     Resume,
     Play,
@@ -42,11 +57,57 @@ pub enum Command {
     Prev,
     Remove(TrackIndexSelection),
     Seek(Duration),
+    Loop,
     Now,
+    Info(TrackIndex),
     Reverse(TrackIndexSelection),
     Quota(Option<usize>),
     Move(TrackIndexSelection, TrackIndex),
     When(TrackIndex),
+    Status,
+    Undo,
+    Redo,
+    /// number of recent plays to show
+    History(usize),
+    /// all categories if there's none
+    Stats(Option<StatsCategory>, StatsPeriod),
+    Playlist(PlaylistCommand),
+    SharedPlaylist(SharedPlaylistCommand),
+    /// enqueues the tracks of the shared playlist with the given name
+    PlaySharedPlaylist(String),
+}
+
+/// Sub-commands managing the issuer's personal playlists.
+pub enum PlaylistCommand {
+    Save {
+        name: String,
+        tracks: TrackIndexSelection,
+    },
+    Load(String),
+    List,
+    Delete(String),
+}
+
+/// Sub-commands editing or showing the guild's shared playlists.
+pub enum SharedPlaylistCommand {
+    /// the selected tracks are optional
+    Create {
+        name: String,
+        tracks: TrackIndexSelection,
+    },
+    Add {
+        name: String,
+        tracks: TrackIndexSelection,
+    },
+    /// 0-based positions within the playlist
+    Move {
+        name: String,
+        from: usize,
+        to: usize,
+    },
+    Show(String),
+    List,
+    Delete(String),
 }
 
 pub type CommandResult<T = ()> = Result<T, CommandError>;
@@ -105,33 +166,26 @@ impl Command {
                     Command::Help(HelpTopic::General)
                 }
             }
-            CMD_JOIN => Command::Join,
+            CMD_JOIN => Command::Join(args.map(ToOwned::to_owned)),
             CMD_LEAVE => Command::Leave,
-            CMD_ENQUEUE => {
-                let mut tracks: Vec<(String, Option<String>)> = Vec::new();
-                let args = args.unwrap_or_default();
-                for line in args.lines() {
-                    let mut parts = line
-                        .trim_matches(&[' ', '\t'][..])
-                        .splitn(2, &[' ', '\t'][..]);
-                    let url = parts.next().unwrap().trim_matches(&[' ', '`', '\t'][..]);
-                    if url.is_empty() {
-                        continue;
+            CMD_ENQUEUE => Command::Enqueue(parse_track_refs(args.unwrap_or_default())),
+            CMD_ENQUEUE_NEXT => Command::Insert(
+                TrackIndex::Current(1),
+                parse_track_refs(args.unwrap_or_default()),
+            ),
+            CMD_ENQUEUE_AT => {
+                let mut parts = args.unwrap_or_default().splitn(2, &[' ', '\n', '\t'][..]);
+                match parts.next().unwrap().parse() {
+                    Ok(index) => {
+                        Command::Insert(index, parse_track_refs(parts.next().unwrap_or_default()))
+                    }
+                    Err(_err) => {
+                        reply_channel
+                            .print("please specify a valid destination".to_string())
+                            .await;
+                        Command::Help(HelpTopic::EnqueueAt)
                     }
-                    let comment = parts
-                        .next()
-                        .map(|comment| comment.trim_matches(&[' ', '`', '\t'][..]));
-                    let comment = comment.and_then(|comment| {
-                        if comment.is_empty() {
-                            None
-                        } else {
-                            Some(comment.to_owned())
-                        }
-                    });
-                    tracks.push((url.to_owned(), comment));
                 }
-
-                Command::Enqueue(tracks)
             }
             CMD_PAUSE => Command::Pause,
             CMD_RESUME => Command::Resume,
@@ -148,7 +202,7 @@ impl Command {
                 Ok(tracks) => {
                     if tracks.is_empty() {
                         let start = TrackIndex::Current(-2);
-                        let end = TrackIndex::Current(15);
+                        let end = TrackIndex::Current(12);
                         Command::Print(TrackIndexSelection(vec![TrackIndexRange::Range(
                             start, end,
                         )]))
@@ -225,7 +279,16 @@ impl Command {
                     Command::Help(HelpTopic::Seek)
                 }
             }
+            CMD_LOOP => Command::Loop,
             CMD_NOW => Command::Now,
+            CMD_INFO => match args.map(str::parse) {
+                Some(Ok(index)) => Command::Info(index),
+                Some(Err(err)) => {
+                    reply_channel.print(err).await;
+                    Command::Help(HelpTopic::Info)
+                }
+                None => Command::Info(TrackIndex::Current(0)),
+            },
             CMD_REVERSE => match TrackIndexSelection::parse_str(args.unwrap_or_default()) {
                 Ok(tracks) => {
                     if tracks.is_empty() {
@@ -306,6 +369,166 @@ impl Command {
                     Command::Help(HelpTopic::When)
                 }
             },
+            CMD_STATUS => Command::Status,
+            CMD_UNDO => Command::Undo,
+            CMD_REDO => Command::Redo,
+            CMD_HISTORY => match args.map(str::parse::<usize>) {
+                Some(Ok(count)) if count > 0 => Command::History(count),
+                Some(_) => {
+                    reply_channel
+                        .print("please specify a valid number of tracks".to_string())
+                        .await;
+                    Command::Help(HelpTopic::History)
+                }
+                None => Command::History(10),
+            },
+            CMD_PLAYLIST => {
+                let mut parts = args.unwrap_or_default().splitn(3, &[' ', '\n', '\t'][..]);
+                let sub_command = parts.next().unwrap_or_default();
+                let name = parts.next().map(str::trim).filter(|name| !name.is_empty());
+                match (sub_command, name) {
+                    ("save", Some(name)) => {
+                        match TrackIndexSelection::parse_str(parts.next().unwrap_or_default()) {
+                            Ok(tracks) => {
+                                let tracks = if tracks.is_empty() {
+                                    TrackIndexSelection(vec![TrackIndexRange::Range(
+                                        TrackIndex::Start(0),
+                                        TrackIndex::End(0),
+                                    )])
+                                } else {
+                                    tracks
+                                };
+                                Command::Playlist(PlaylistCommand::Save {
+                                    name: name.to_owned(),
+                                    tracks,
+                                })
+                            }
+                            Err(err) => {
+                                reply_channel.print(err).await;
+                                Command::Help(HelpTopic::Playlist)
+                            }
+                        }
+                    }
+                    ("load", Some(name)) => {
+                        Command::Playlist(PlaylistCommand::Load(name.to_owned()))
+                    }
+                    ("delete", Some(name)) => {
+                        Command::Playlist(PlaylistCommand::Delete(name.to_owned()))
+                    }
+                    ("list", _) | ("", None) => Command::Playlist(PlaylistCommand::List),
+                    ("save" | "load" | "delete", None) => {
+                        reply_channel
+                            .print("please specify the name of the playlist".to_string())
+                            .await;
+                        Command::Help(HelpTopic::Playlist)
+                    }
+                    _ => {
+                        reply_channel
+                            .print(format!("Unknown playlist command: {sub_command}"))
+                            .await;
+                        Command::Help(HelpTopic::Playlist)
+                    }
+                }
+            }
+            CMD_SHARED_PLAYLIST => {
+                let mut parts = args.unwrap_or_default().splitn(3, &[' ', '\n', '\t'][..]);
+                let sub_command = parts.next().unwrap_or_default();
+                let name = parts.next().map(str::trim).filter(|name| !name.is_empty());
+                let rest = parts.next().unwrap_or_default();
+                match (sub_command, name) {
+                    ("create" | "add", Some(name)) => match TrackIndexSelection::parse_str(rest) {
+                        Ok(tracks) if sub_command == "create" => {
+                            Command::SharedPlaylist(SharedPlaylistCommand::Create {
+                                name: name.to_owned(),
+                                tracks,
+                            })
+                        }
+                        Ok(tracks) if !tracks.is_empty() => {
+                            Command::SharedPlaylist(SharedPlaylistCommand::Add {
+                                name: name.to_owned(),
+                                tracks,
+                            })
+                        }
+                        Ok(_) => {
+                            reply_channel
+                                .print("please specify the tracks to add".to_string())
+                                .await;
+                            Command::Help(HelpTopic::SharedPlaylist)
+                        }
+                        Err(err) => {
+                            reply_channel.print(err).await;
+                            Command::Help(HelpTopic::SharedPlaylist)
+                        }
+                    },
+                    ("move", Some(name)) => {
+                        let positions: Vec<Option<usize>> = rest
+                            .split_whitespace()
+                            .map(|position| {
+                                position
+                                    .parse::<usize>()
+                                    .ok()
+                                    .and_then(|position| position.checked_sub(1))
+                            })
+                            .collect();
+                        if let [Some(from), Some(to)] = positions[..] {
+                            Command::SharedPlaylist(SharedPlaylistCommand::Move {
+                                name: name.to_owned(),
+                                from,
+                                to,
+                            })
+                        } else {
+                            reply_channel
+                                .print(
+                                    "please specify the number of the track to move and its new position"
+                                        .to_string(),
+                                )
+                                .await;
+                            Command::Help(HelpTopic::SharedPlaylist)
+                        }
+                    }
+                    ("show", Some(name)) => {
+                        Command::SharedPlaylist(SharedPlaylistCommand::Show(name.to_owned()))
+                    }
+                    ("play", Some(name)) => Command::PlaySharedPlaylist(name.to_owned()),
+                    ("delete", Some(name)) => {
+                        Command::SharedPlaylist(SharedPlaylistCommand::Delete(name.to_owned()))
+                    }
+                    ("list", _) | ("", None) => Command::SharedPlaylist(SharedPlaylistCommand::List),
+                    ("create" | "add" | "move" | "show" | "play" | "delete", None) => {
+                        reply_channel
+                            .print("please specify the name of the playlist".to_string())
+                            .await;
+                        Command::Help(HelpTopic::SharedPlaylist)
+                    }
+                    _ => {
+                        reply_channel
+                            .print(format!("Unknown shared playlist command: {sub_command}"))
+                            .await;
+                        Command::Help(HelpTopic::SharedPlaylist)
+                    }
+                }
+            }
+            CMD_STATS => {
+                let mut category = None;
+                let mut period = StatsPeriod::LastDays(7);
+                let mut error = None;
+                for arg in args.unwrap_or_default().split_whitespace() {
+                    if let Ok(arg) = arg.parse() {
+                        category = Some(arg);
+                    } else {
+                        match arg.parse() {
+                            Ok(arg) => period = arg,
+                            Err(err) => error = Some(err),
+                        }
+                    }
+                }
+                if let Some(err) = error {
+                    reply_channel.print(err).await;
+                    Command::Help(HelpTopic::Stats)
+                } else {
+                    Command::Stats(category, period)
+                }
+            }
 
             _ => {
                 reply_channel
@@ -318,11 +541,24 @@ impl Command {
 
     pub fn requires_vc(&self) -> bool {
         match *self {
-            Command::Help(_) | Command::Print(_) | Command::Now | Command::When(_) => false,
+            Command::Help(_)
+            | Command::Print(_)
+            | Command::Now
+            | Command::Info(_)
+            | Command::When(_)
+            | Command::Status
+            | Command::History(_)
+            | Command::Stats(_, _)
+            | Command::Playlist(
+                PlaylistCommand::Save { .. } | PlaylistCommand::List | PlaylistCommand::Delete(_),
+            )
+            | Command::SharedPlaylist(_)
+            | Command::Join(Some(_)) => false,
 
-            Command::Join
+            Command::Join(None)
             | Command::Leave
             | Command::Enqueue(_)
+            | Command::Insert(_, _)
             | Command::Pause
             | Command::Resume
             | Command::Play
@@ -332,9 +568,40 @@ impl Command {
             | Command::Prev
             | Command::Remove(_)
             | Command::Seek(_)
+            | Command::Loop
             | Command::Reverse(_)
             | Command::Quota(_)
-            | Command::Move(_, _) => true,
+            | Command::Move(_, _)
+            | Command::Undo
+            | Command::Redo
+            | Command::Playlist(PlaylistCommand::Load(_))
+            | Command::PlaySharedPlaylist(_) => true,
+        }
+    }
+}
+
+/// One track reference per line; each may be followed by a comment.
+fn parse_track_refs(args: &str) -> Vec<(String, Option<String>)> {
+    let mut tracks: Vec<(String, Option<String>)> = Vec::new();
+    for line in args.lines() {
+        let mut parts = line
+            .trim_matches(&[' ', '\t'][..])
+            .splitn(2, &[' ', '\t'][..]);
+        let url = parts.next().unwrap().trim_matches(&[' ', '`', '\t'][..]);
+        if url.is_empty() {
+            continue;
         }
+        let comment = parts
+            .next()
+            .map(|comment| comment.trim_matches(&[' ', '`', '\t'][..]));
+        let comment = comment.and_then(|comment| {
+            if comment.is_empty() {
+                None
+            } else {
+                Some(comment.to_owned())
+            }
+        });
+        tracks.push((url.to_owned(), comment));
     }
+    tracks
 }
@@ -1,19 +1,28 @@
+use futures::{FutureExt, StreamExt};
 use lazy_static::lazy_static;
 use std::{
     collections::{HashMap, HashSet},
-    fmt,
+    env, fmt,
     fmt::Write,
-    sync::Arc,
-    time::Duration,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex as StdMutex,
+    },
+    time::{Duration, Instant},
 };
 
 use serenity::{
     client::Context,
     http::Http,
     model::{
-        channel::{Message, ReactionType},
-        id::{ChannelId, GuildId},
-        prelude::User,
+        application::interaction::{
+            message_component::MessageComponentInteraction, InteractionResponseType,
+        },
+        channel::{Channel, ChannelType, Message, Reaction, ReactionType},
+        guild::Guild,
+        id::{ChannelId, GuildId, MessageId, UserId},
+        prelude::{User, VoiceState},
     },
 };
 
@@ -22,19 +31,23 @@ use crate::{
     command::*,
     help::HelpTopic,
     message::MessageChannel,
-    queue::Queue,
+    panel::{PanelAction, PANEL_REFRESH_INTERVAL},
+    playlist::{Playlist, PlaylistStore},
+    queue::{PauseReason, Queue},
+    queue_history::CommandId,
+    queue_page,
+    stage::Stage,
+    stats::{StatsCategory, StatsPeriod},
     track::{
-        audiotool::{
-            AudiotoolAlbumDispatcher, AudiotoolGenreChartsDispatcher,
-            AudiotoolSingleChartsDispatcher, AudiotoolTrackRefDispatcher,
-        },
+        self,
         selection::{TrackIndex, TrackIndexSelection},
-        youtube::YoutubeTrackRefDispatcher,
-        TrackRefDispatcher,
+        Track,
     },
+    AUDIOTOOL_HTTP_CLIENT,
 };
 
-use tokio::sync::Mutex;
+use songbird::Songbird;
+use tokio::sync::{mpsc, oneshot, Mutex};
 
 lazy_static! {
     pub static ref GUILD_STATES: GuildStates = GuildStates::new();
@@ -53,69 +66,430 @@ impl GuildStates {
 
     pub async fn get_guild_state(&self, guild_id: GuildId) -> GuildStateHandle {
         let mut queues = self.guild_states.lock().await;
-        queues
+        let handle = queues
             .entry(guild_id)
-            .or_insert_with(|| GuildStateHandle {
-                guild_id,
-                state: Arc::new(Mutex::new(GuildState::new(guild_id))),
-            })
-            .clone()
+            .or_insert_with(|| GuildStateHandle::spawn(guild_id));
+        // the worker only stops if it crashed; start over rather than rejecting every command
+        if handle.sender.is_closed() {
+            eprintln!("worker of guild {guild_id} is gone; starting a new one");
+            *handle = GuildStateHandle::spawn(guild_id);
+        }
+        handle.clone()
+    }
+}
+
+/// Upper limit for waiting on the execution of a single command by the guild's worker.
+///
+/// The command is skipped if the worker doesn't get to it in time; once started it completes
+/// regardless, but its result isn't reported anymore.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+static NEXT_COMMAND_ID: AtomicU64 = AtomicU64::new(0);
+
+fn next_command_id() -> CommandId {
+    NEXT_COMMAND_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Track page URLs along with their optional comments; as given to the enqueue command.
+type TrackRefs = Vec<(String, Option<String>)>;
+
+/// Keeps the listing of a shared playlist below Discord's message size limit.
+const MAX_SHOWN_PLAYLIST_TRACKS: usize = 20;
+
+/// environment variable overriding how many seconds the bot stays in a voice channel without
+/// listeners; `0` disables leaving for this reason
+pub const ENV_ALONE_TIMEOUT: &str = "AUTO_LEAVE_ALONE_TIMEOUT";
+/// environment variable overriding how many seconds the bot stays in a voice channel without
+/// playing anything; `0` disables leaving for this reason
+pub const ENV_IDLE_TIMEOUT: &str = "AUTO_LEAVE_IDLE_TIMEOUT";
+
+const DEFAULT_ALONE_TIMEOUT_SECS: u64 = 5 * 60;
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 30 * 60;
+
+/// When the bot leaves its voice channel on its own.
+#[derive(Clone, Copy, Debug)]
+struct AutoLeavePolicy {
+    /// how long to wait for listeners to return after everybody left the voice channel
+    alone_timeout: Option<Duration>,
+    /// how long to stay while nothing is being played
+    idle_timeout: Option<Duration>,
+}
+
+impl AutoLeavePolicy {
+    fn from_env() -> Self {
+        let timeout = |name: &str, default: Duration| match env::var(name) {
+            Ok(secs) => match secs.parse::<u64>() {
+                Ok(0) => None,
+                Ok(secs) => Some(Duration::from_secs(secs)),
+                Err(err) => {
+                    eprintln!("ignoring invalid {name}={secs}: {err}");
+                    Some(default)
+                }
+            },
+            Err(_) => Some(default),
+        };
+        let defaults = Self::default();
+        Self {
+            alone_timeout: defaults
+                .alone_timeout
+                .and_then(|default| timeout(ENV_ALONE_TIMEOUT, default)),
+            idle_timeout: defaults
+                .idle_timeout
+                .and_then(|default| timeout(ENV_IDLE_TIMEOUT, default)),
+        }
+    }
+}
+
+impl Default for AutoLeavePolicy {
+    fn default() -> Self {
+        Self {
+            alone_timeout: Some(Duration::from_secs(DEFAULT_ALONE_TIMEOUT_SECS)),
+            idle_timeout: Some(Duration::from_secs(DEFAULT_IDLE_TIMEOUT_SECS)),
+        }
     }
 }
 
+/// environment variable overriding after how many seconds confirmations like "Removed 3 track(s)"
+/// are deleted again; `0` keeps them
+pub const ENV_STATUS_LIFETIME: &str = "CLEANUP_STATUS_LIFETIME";
+/// environment variable which makes the bot delete successful commands when set to `true`
+pub const ENV_DELETE_COMMANDS: &str = "CLEANUP_DELETE_COMMANDS";
+
+const DEFAULT_STATUS_LIFETIME_SECS: u64 = 60;
+
+/// Which messages the bot deletes again to keep busy channels tidy.
+///
+/// There's only ever a single now-playing panel per guild, so it doesn't need to be cleaned up.
+#[derive(Clone, Copy, Debug)]
+struct CleanupPolicy {
+    /// how long to keep transient confirmations
+    status_lifetime: Option<Duration>,
+    /// whether to delete the users' commands once they have been executed successfully
+    delete_commands: bool,
+}
+
+impl CleanupPolicy {
+    fn from_env() -> Self {
+        let defaults = Self::default();
+        let status_lifetime = match env::var(ENV_STATUS_LIFETIME) {
+            Ok(secs) => match secs.parse::<u64>() {
+                Ok(0) => None,
+                Ok(secs) => Some(Duration::from_secs(secs)),
+                Err(err) => {
+                    eprintln!("ignoring invalid {ENV_STATUS_LIFETIME}={secs}: {err}");
+                    defaults.status_lifetime
+                }
+            },
+            Err(_) => defaults.status_lifetime,
+        };
+        let delete_commands = match env::var(ENV_DELETE_COMMANDS) {
+            Ok(flag) => match flag.parse::<bool>() {
+                Ok(flag) => flag,
+                Err(err) => {
+                    eprintln!("ignoring invalid {ENV_DELETE_COMMANDS}={flag}: {err}");
+                    defaults.delete_commands
+                }
+            },
+            Err(_) => defaults.delete_commands,
+        };
+        Self {
+            status_lifetime,
+            delete_commands,
+        }
+    }
+}
+
+impl Default for CleanupPolicy {
+    fn default() -> Self {
+        Self {
+            status_lifetime: Some(Duration::from_secs(DEFAULT_STATUS_LIFETIME_SECS)),
+            delete_commands: false,
+        }
+    }
+}
+
+/// environment variable naming the role whose members aren't limited by the quota when placing
+/// tracks; empty to only trust members who may manage channels
+pub const ENV_DJ_ROLE: &str = "DJ_ROLE";
+
+/// Who is allowed to place tracks anywhere in the queue regardless of the quota.
+#[derive(Clone, Debug)]
+struct DjPolicy {
+    /// name of the DJ role; compared case-insensitively
+    role: Option<String>,
+}
+
+impl DjPolicy {
+    fn from_env() -> Self {
+        match env::var(ENV_DJ_ROLE) {
+            Ok(role) if role.trim().is_empty() => Self { role: None },
+            Ok(role) => Self {
+                role: Some(role.trim().to_owned()),
+            },
+            Err(_) => Self::default(),
+        }
+    }
+}
+
+impl Default for DjPolicy {
+    fn default() -> Self {
+        Self {
+            role: Some("DJ".to_owned()),
+        }
+    }
+}
+
+/// Messages processed by a guild's worker task; one at a time in the order they were sent.
+enum GuildMessage {
+    /// a user command whose result will be sent back to the issuer; the issuer is recorded when
+    /// the command skips a track
+    Command(
+        GuildCommand,
+        UserId,
+        CommandId,
+        oneshot::Sender<CommandResult>,
+    ),
+    IsCommandChannel(ChannelId, oneshot::Sender<bool>),
+    IsPanelMessage(MessageId, oneshot::Sender<bool>),
+    /// track references of a shared playlist; to be enqueued by the sender
+    SharedPlaylistTracks(String, oneshot::Sender<CommandResult<TrackRefs>>),
+    /// the current track reached its end (voice event)
+    TrackEnd,
+    /// somebody joined, left or moved between voice channels of the guild
+    VoiceStateUpdate {
+        ctx: Context,
+        user_id: UserId,
+        channel_id: Option<ChannelId>,
+    },
+    /// the voice connection broke down; `None` if this has been requested (e.g. by leaving)
+    DriverDisconnect(Option<String>),
+    DriverReconnect,
+    Print(String),
+    SetHttp(Arc<Http>),
+    SetDefaultOutputChannel(ChannelId),
+    AddCommandChannel(ChannelId),
+}
+
+impl GuildMessage {
+    fn name(&self) -> &'static str {
+        match self {
+            GuildMessage::Command(command, ..) => command.name(),
+            GuildMessage::IsCommandChannel(..) => "is-command-channel",
+            GuildMessage::IsPanelMessage(..) => "is-panel-message",
+            GuildMessage::SharedPlaylistTracks(..) => "shared-playlist-tracks",
+            GuildMessage::TrackEnd => "track-end",
+            GuildMessage::VoiceStateUpdate { .. } => "voice-state-update",
+            GuildMessage::DriverDisconnect(_) => "driver-disconnect",
+            GuildMessage::DriverReconnect => "driver-reconnect",
+            GuildMessage::Print(_) => "print-default",
+            GuildMessage::SetHttp(_) => "set-http",
+            GuildMessage::SetDefaultOutputChannel(_) => "set-default-output-channel",
+            GuildMessage::AddCommandChannel(_) => "add-command-channel",
+        }
+    }
+
+    /// Whether the message might start or stop the playback or change the voice connection.
+    fn affects_playback(&self) -> bool {
+        match self {
+            GuildMessage::Command(..)
+            | GuildMessage::TrackEnd
+            | GuildMessage::VoiceStateUpdate { .. }
+            | GuildMessage::DriverDisconnect(_)
+            | GuildMessage::DriverReconnect => true,
+            GuildMessage::IsCommandChannel(..)
+            | GuildMessage::IsPanelMessage(..)
+            | GuildMessage::SharedPlaylistTracks(..)
+            | GuildMessage::Print(_)
+            | GuildMessage::SetHttp(_)
+            | GuildMessage::SetDefaultOutputChannel(_)
+            | GuildMessage::AddCommandChannel(_) => false,
+        }
+    }
+}
+
+enum GuildCommand {
+    Join {
+        out: MessageChannel,
+        ctx: Context,
+        msg: Box<Message>,
+        channel: Option<String>,
+        is_dj: bool,
+    },
+    Leave {
+        out: MessageChannel,
+        ctx: Context,
+    },
+    Append(Box<dyn Track>),
+    Insert {
+        out: MessageChannel,
+        tracks: Vec<Box<dyn Track>>,
+        index: TrackIndex,
+        is_dj: bool,
+    },
+    Pause,
+    Resume,
+    Play,
+    Stop,
+    Print {
+        out: MessageChannel,
+        tracks: TrackIndexSelection,
+    },
+    /// another page of a printed queue has been requested
+    TurnQueuePage {
+        http: Arc<Http>,
+        component: Box<MessageComponentInteraction>,
+        start: usize,
+    },
+    Goto(TrackIndex),
+    Next,
+    Prev,
+    Remove {
+        out: MessageChannel,
+        tracks: TrackIndexSelection,
+    },
+    Seek(Duration),
+    Loop {
+        out: MessageChannel,
+    },
+    Now {
+        out: MessageChannel,
+    },
+    Info {
+        out: MessageChannel,
+        index: TrackIndex,
+    },
+    Reverse(TrackIndexSelection),
+    SetQuota(Option<usize>),
+    PrintQuota {
+        out: MessageChannel,
+    },
+    Move {
+        out: MessageChannel,
+        tracks: TrackIndexSelection,
+        index: TrackIndex,
+    },
+    When {
+        out: MessageChannel,
+        index: TrackIndex,
+    },
+    History {
+        out: MessageChannel,
+        count: usize,
+    },
+    Stats {
+        out: MessageChannel,
+        category: Option<StatsCategory>,
+        period: StatsPeriod,
+    },
+    SavePlaylist {
+        out: MessageChannel,
+        user_id: UserId,
+        name: String,
+        tracks: TrackIndexSelection,
+    },
+    SharedPlaylist {
+        out: MessageChannel,
+        user_id: UserId,
+        is_dj: bool,
+        command: SharedPlaylistCommand,
+    },
+    Undo {
+        out: MessageChannel,
+    },
+    Redo {
+        out: MessageChannel,
+    },
+}
+
+impl GuildCommand {
+    fn name(&self) -> &'static str {
+        match self {
+            GuildCommand::Join { .. } => CMD_JOIN,
+            GuildCommand::Leave { .. } => CMD_LEAVE,
+            GuildCommand::Append(_) => CMD_ENQUEUE,
+            GuildCommand::Insert { .. } => CMD_ENQUEUE_AT,
+            GuildCommand::Pause => CMD_PAUSE,
+            GuildCommand::Resume => CMD_RESUME,
+            GuildCommand::Play => CMD_PLAY,
+            GuildCommand::Stop => CMD_STOP,
+            GuildCommand::Print { .. } | GuildCommand::TurnQueuePage { .. } => CMD_PRINT,
+            GuildCommand::Goto(_) => CMD_GOTO,
+            GuildCommand::Next => CMD_NEXT,
+            GuildCommand::Prev => CMD_PREV,
+            GuildCommand::Remove { .. } => CMD_REMOVE,
+            GuildCommand::Seek(_) => CMD_SEEK,
+            GuildCommand::Loop { .. } => CMD_LOOP,
+            GuildCommand::Now { .. } => CMD_NOW,
+            GuildCommand::Info { .. } => CMD_INFO,
+            GuildCommand::Reverse(_) => CMD_REVERSE,
+            GuildCommand::SetQuota(_) | GuildCommand::PrintQuota { .. } => CMD_QUOTA,
+            GuildCommand::Move { .. } => CMD_MOVE,
+            GuildCommand::When { .. } => CMD_WHEN,
+            GuildCommand::History { .. } => CMD_HISTORY,
+            GuildCommand::Stats { .. } => CMD_STATS,
+            GuildCommand::SavePlaylist { .. } => CMD_PLAYLIST,
+            GuildCommand::SharedPlaylist { .. } => CMD_SHARED_PLAYLIST,
+            GuildCommand::Undo { .. } => CMD_UNDO,
+            GuildCommand::Redo { .. } => CMD_REDO,
+        }
+    }
+}
+
+/// What a guild's worker is up to; shared with all handles so it can be inspected while the
+/// worker is busy.
+#[derive(Default)]
+struct WorkerStatus {
+    pending: usize,
+    busy_with: Option<(&'static str, Instant)>,
+    processed: u64,
+    cancelled: u64,
+}
+
+impl fmt::Display for WorkerStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.busy_with {
+            Some((name, since)) => write!(
+                f,
+                "Guild worker: busy with `{name}` for {}s",
+                since.elapsed().as_secs()
+            )?,
+            None => write!(f, "Guild worker: idle")?,
+        }
+        write!(
+            f,
+            "; {} pending, {} processed, {} cancelled",
+            self.pending, self.processed, self.cancelled
+        )
+    }
+}
+
+/// Cheap handle to a guild's state.
+///
+/// The state itself is owned by a worker task which processes all requests sequentially.
 #[derive(Clone)]
 pub struct GuildStateHandle {
-    #[allow(unused)]
     guild_id: GuildId,
-    state: Arc<Mutex<GuildState>>,
+    sender: mpsc::UnboundedSender<GuildMessage>,
+    status: Arc<StdMutex<WorkerStatus>>,
+    cleanup: CleanupPolicy,
+    dj: DjPolicy,
 }
 
 impl GuildStateHandle {
     pub async fn handle_command_line(&self, command_line: &str, ctx: &Context, msg: &Message) {
         match self.execute_command(command_line, ctx, msg).await {
             Ok(reaction) => {
-                if let Some(reaction) = reaction {
+                if self.cleanup.delete_commands {
+                    if let Err(err) = msg.delete(ctx).await {
+                        eprintln!("failed to delete command: {err}");
+                    }
+                } else if let Some(reaction) = reaction {
                     let _ = msg.react(ctx, reaction).await;
                 }
             }
             Err(err) => {
-                let reply_channel = MessageChannel::new(msg.channel_id, ctx.http.clone());
-                match err {
-                    CommandError::Usage { message, topic } => {
-                        reply_channel
-                            .print(format!("{}\n{}", message, topic.message()))
-                            .await;
-                        //reply_channel.print(topic.message()).await;
-                        // if let Some(topic) = topic {
-                        //     match topic.parse::<HelpTopic>() {
-                        //     }
-                        //     let _ = HelpTopicCommand::help_topic(&reply_channel, topic).await;
-                        // } else {
-                        //     let _ = Command::help(&reply_channel).await;
-                        // }
-                    }
-                    CommandError::Discord(message) => {
-                        let message = format!("Internal error: {message}");
-                        reply_channel.print(&message).await;
-                        println!("{message}");
-                    }
-                    CommandError::UserVoiceChannelRequired => {
-                        reply_channel
-                            .print("You need to be in a voice channel to use this command")
-                            .await;
-                    }
-                    CommandError::BotVoiceChannelRequired => {
-                        reply_channel.print("I need to be in a voice chat to play a track. Use the `join` command to invite me.").await;
-                    }
-                    CommandError::Execution(message) => {
-                        reply_channel.print(message).await;
-                    }
-                    CommandError::NotInCommandChannel => {
-                        reply_channel
-                            .print("This channel isn't available for bot commands.")
-                            .await;
-                    }
-                }
+                let reply_channel = self.reply_channel(ctx, msg.channel_id);
+                reply_channel.reply(msg.id, describe_error(err)).await;
                 let _ = msg.react(ctx, ReactionType::Unicode("🚫".to_owned())).await;
             }
         }
@@ -132,21 +506,11 @@ impl GuildStateHandle {
             return Err(CommandError::NotInCommandChannel);
         }
 
-        let reply_channel = MessageChannel::new(msg.channel_id, ctx.http.clone());
+        let reply_channel = self.reply_channel(ctx, msg.channel_id);
 
         let command = Command::from_str(command_line, &reply_channel).await;
         if command.requires_vc() {
-            let guild = msg
-                .guild(&ctx.cache)
-                .ok_or_else(|| CommandError::Discord("Could not retrieve guild".to_owned()))?;
-            let is_in_voice_channel = guild
-                .voice_states
-                .get(&msg.author.id)
-                .and_then(|voice_state| voice_state.channel_id)
-                .is_some();
-            if !is_in_voice_channel {
-                return Err(CommandError::UserVoiceChannelRequired);
-            }
+            self.require_voice_channel(ctx, msg.author.id)?;
         }
 
         match command {
@@ -154,146 +518,466 @@ impl GuildStateHandle {
                 reply_channel.print(topic.message()).await;
                 Ok(None)
             }
-            Command::Join => self
-                .command_join(&reply_channel, ctx, msg)
-                .await
-                .map(|()| Some(ReactionType::Unicode("🎧".to_owned()))),
+            Command::Join(channel) => {
+                let is_dj = self.is_dj(ctx, msg.author.id).await;
+                self.command_join(&reply_channel, ctx, msg, channel, is_dj)
+                    .await
+                    .map(|()| Some(ReactionType::Unicode("🎧".to_owned())))
+            }
             Command::Leave => self
-                .command_leave(&reply_channel, ctx)
+                .command_leave(&reply_channel, ctx, msg.author.id)
                 .await
                 .map(|()| Some(ReactionType::Unicode("👋".to_owned()))),
             Command::Enqueue(tracks) => self
                 .command_enqueue(&reply_channel, tracks, &msg.author)
                 .await
                 .map(|()| Some(ReactionType::Unicode("✅".to_owned()))),
+            Command::Insert(index, tracks) => {
+                let is_dj = self.is_dj(ctx, msg.author.id).await;
+                self.command_insert(&reply_channel, tracks, &msg.author, index, is_dj)
+                    .await
+                    .map(|()| Some(ReactionType::Unicode("✅".to_owned())))
+            }
             Command::Pause => self
-                .command_pause()
+                .command_pause(msg.author.id)
                 .await
                 .map(|()| Some(ReactionType::Unicode("⏸".to_owned()))),
             Command::Resume => self
-                .command_resume()
+                .command_resume(msg.author.id)
                 .await
                 .map(|()| Some(ReactionType::Unicode("⏯".to_owned()))),
             Command::Play => self
-                .command_play()
+                .command_play(msg.author.id)
                 .await
                 .map(|()| Some(ReactionType::Unicode("🔊".to_owned()))),
             Command::Stop => self
-                .command_stop()
+                .command_stop(msg.author.id)
                 .await
                 .map(|()| Some(ReactionType::Unicode("⏹".to_owned()))),
-            Command::Print(ref selection) => self
-                .command_print(&reply_channel, selection)
+            Command::Print(selection) => self
+                .command_print(&reply_channel, selection, msg.author.id)
                 .await
                 .map(|()| None),
             Command::Goto(index) => self
-                .command_goto(index)
+                .command_goto(index, msg.author.id)
                 .await
                 .map(|()| Some(ReactionType::Unicode("⏬".to_owned()))),
             Command::Next => self
-                .command_next()
+                .command_next(msg.author.id)
                 .await
                 .map(|()| Some(ReactionType::Unicode("⏭".to_owned()))),
             Command::Prev => self
-                .command_prev()
+                .command_prev(msg.author.id)
                 .await
                 .map(|()| Some(ReactionType::Unicode("⏮".to_owned()))),
-            Command::Remove(ref selection) => self
-                .command_remove(&reply_channel, selection)
+            Command::Remove(selection) => self
+                .command_remove(&reply_channel, selection, msg.author.id)
                 .await
                 .map(|()| Some(ReactionType::Unicode("❎".to_owned()))),
             Command::Seek(position) => self
-                .command_seek(position)
+                .command_seek(position, msg.author.id)
                 .await
                 .map(|()| Some(ReactionType::Unicode("🔎".to_owned()))),
-            Command::Now => self.command_now(&reply_channel).await.map(|()| None),
-            Command::Reverse(ref selection) => self
-                .command_reverse(selection)
+            Command::Loop => self
+                .command_loop(&reply_channel, msg.author.id)
+                .await
+                .map(|()| Some(ReactionType::Unicode("🔁".to_owned()))),
+            Command::Now => self
+                .command_now(&reply_channel, msg.author.id)
+                .await
+                .map(|()| None),
+            Command::Info(index) => self
+                .command_info(&reply_channel, index, msg.author.id)
+                .await
+                .map(|()| None),
+            Command::Reverse(selection) => self
+                .command_reverse(selection, msg.author.id)
                 .await
                 .map(|()| Some(ReactionType::Unicode("🔃".to_owned()))),
             Command::Quota(quota) => {
                 if let Some(quota) = quota {
                     let quota = if quota == 0 { None } else { Some(quota) };
-                    self.command_set_quota(quota)
+                    self.command_set_quota(quota, msg.author.id)
                         .await
                         .map(|()| Some(ReactionType::Unicode("🛑".to_owned())))
                 } else {
-                    self.command_print_quota(&reply_channel)
+                    self.command_print_quota(&reply_channel, msg.author.id)
                         .await
                         .map(|()| None)
                 }
             }
-            Command::Move(ref selection, index) => self
-                .command_move(&reply_channel, selection, index)
+            Command::Move(selection, index) => self
+                .command_move(&reply_channel, selection, index, msg.author.id)
                 .await
                 .map(|()| Some(ReactionType::Unicode("🔀".to_owned()))),
             Command::When(index) => self
-                .command_when(&reply_channel, index)
+                .command_when(&reply_channel, index, msg.author.id)
+                .await
+                .map(|()| None),
+            Command::Status => self.command_status(&reply_channel).await.map(|()| None),
+            Command::History(count) => self
+                .command_history(&reply_channel, count, msg.author.id)
+                .await
+                .map(|()| None),
+            Command::Stats(category, period) => self
+                .command_stats(&reply_channel, category, period, msg.author.id)
                 .await
                 .map(|()| None),
+            Command::Playlist(command) => self
+                .command_playlist(&reply_channel, command, &msg.author)
+                .await
+                .map(|()| Some(ReactionType::Unicode("✅".to_owned()))),
+            Command::PlaySharedPlaylist(name) => {
+                let track_refs = self.shared_playlist_tracks(name).await?;
+                self.command_enqueue(&reply_channel, track_refs, &msg.author)
+                    .await
+                    .map(|()| Some(ReactionType::Unicode("✅".to_owned())))
+            }
+            Command::SharedPlaylist(command) => {
+                // only changes of existing playlists are restricted
+                let is_dj = matches!(
+                    command,
+                    SharedPlaylistCommand::Add { .. }
+                        | SharedPlaylistCommand::Move { .. }
+                        | SharedPlaylistCommand::Delete(_)
+                ) && self.is_dj(ctx, msg.author.id).await;
+                self.request(
+                    msg.author.id,
+                    GuildCommand::SharedPlaylist {
+                        out: reply_channel.clone(),
+                        user_id: msg.author.id,
+                        is_dj,
+                        command,
+                    },
+                )
+                .await
+                .map(|()| None)
+            }
+            Command::Undo => self
+                .command_undo(&reply_channel, msg.author.id)
+                .await
+                .map(|()| Some(ReactionType::Unicode("↩".to_owned()))),
+            Command::Redo => self
+                .command_redo(&reply_channel, msg.author.id)
+                .await
+                .map(|()| Some(ReactionType::Unicode("↪".to_owned()))),
+        }
+    }
+
+    /// Handles a click on one of the buttons of the now-playing panel or a printed queue.
+    pub async fn handle_component(&self, ctx: &Context, component: &MessageComponentInteraction) {
+        if let Some(start) = queue_page::page_start_from_custom_id(&component.data.custom_id) {
+            let result = self
+                .request(
+                    component.user.id,
+                    GuildCommand::TurnQueuePage {
+                        http: ctx.http.clone(),
+                        component: Box::new(component.clone()),
+                        start,
+                    },
+                )
+                .await;
+            if let Err(err) = result {
+                eprintln!("failed to turn the queue page: {err}");
+            }
+            return;
+        }
+
+        let Some(action) = PanelAction::from_custom_id(&component.data.custom_id) else {
+            return;
+        };
+
+        // the panel itself will be updated by the worker
+        if let Err(err) = component
+            .create_interaction_response(&ctx.http, |response| {
+                response.kind(InteractionResponseType::DeferredUpdateMessage)
+            })
+            .await
+        {
+            eprintln!("failed to acknowledge interaction: {err}");
+            return;
+        }
+
+        if let Err(err) = self
+            .execute_action(ctx, component.channel_id, component.user.id, action)
+            .await
+        {
+            let result = component
+                .create_followup_message(&ctx.http, |followup| {
+                    followup.content(describe_error(err)).ephemeral(true)
+                })
+                .await;
+            if let Err(err) = result {
+                eprintln!("failed to send followup message: {err}");
+            }
+        }
+    }
+
+    /// Handles a reaction to the now-playing panel; reactions to other messages are ignored.
+    pub async fn handle_reaction(&self, ctx: &Context, reaction: &Reaction) {
+        let Some(user_id) = reaction.user_id else {
+            return;
+        };
+        if user_id == ctx.cache.current_user_id() {
+            return;
+        }
+        let Some(action) = PanelAction::from_reaction(&reaction.emoji) else {
+            return;
+        };
+        if !self.is_panel_message(reaction.message_id).await {
+            return;
+        }
+
+        let result = self
+            .execute_action(ctx, reaction.channel_id, user_id, action)
+            .await;
+        // allow using the same reaction again
+        if let Err(err) = reaction.delete(ctx).await {
+            eprintln!("failed to remove reaction: {err}");
+        }
+        if let Err(err) = result {
+            let reply_channel = self.reply_channel(ctx, reaction.channel_id);
+            reply_channel.print(describe_error(err)).await;
+        }
+    }
+
+    /// Applies the same checks as `execute_command` before executing the action.
+    async fn execute_action(
+        &self,
+        ctx: &Context,
+        channel_id: ChannelId,
+        user_id: UserId,
+        action: PanelAction,
+    ) -> CommandResult {
+        if !self.is_command_channel(channel_id).await {
+            return Err(CommandError::NotInCommandChannel);
+        }
+        self.require_voice_channel(ctx, user_id)?;
+
+        match action {
+            PanelAction::Prev => self.command_prev(user_id).await,
+            PanelAction::Pause => self.command_pause(user_id).await,
+            PanelAction::Resume => self.command_resume(user_id).await,
+            PanelAction::Play => self.command_play(user_id).await,
+            PanelAction::Next => self.command_next(user_id).await,
+            PanelAction::Stop => self.command_stop(user_id).await,
+            PanelAction::Loop => {
+                self.command_loop(&self.reply_channel(ctx, channel_id), user_id)
+                    .await
+            }
+        }
+    }
+
+    /// Playback may only be controlled by users who are listening.
+    fn require_voice_channel(&self, ctx: &Context, user_id: UserId) -> CommandResult {
+        let guild = ctx
+            .cache
+            .guild(self.guild_id)
+            .ok_or_else(|| CommandError::Discord("Could not retrieve guild".to_owned()))?;
+        let is_in_voice_channel = guild
+            .voice_states
+            .get(&user_id)
+            .and_then(|voice_state| voice_state.channel_id)
+            .is_some();
+        if is_in_voice_channel {
+            Ok(())
+        } else {
+            Err(CommandError::UserVoiceChannelRequired)
+        }
+    }
+
+    /// Whether the user has the DJ role or may manage the guild's channels.
+    async fn is_dj(&self, ctx: &Context, user_id: UserId) -> bool {
+        let Some(guild) = ctx.cache.guild(self.guild_id) else {
+            return false;
+        };
+        if let Ok(permissions) = guild.member_permissions(ctx, user_id).await {
+            if permissions.manage_channels() {
+                return true;
+            }
+        }
+        let (Some(role_name), Ok(member)) = (&self.dj.role, guild.member(ctx, user_id).await)
+        else {
+            return false;
+        };
+        member.roles.iter().any(|role_id| {
+            guild
+                .roles
+                .get(role_id)
+                .is_some_and(|role| role.name.eq_ignore_ascii_case(role_name))
+        })
+    }
+
+    fn spawn(guild_id: GuildId) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let status = Arc::new(StdMutex::new(WorkerStatus::default()));
+        let cleanup = CleanupPolicy::from_env();
+        tokio::spawn(GuildState::new(guild_id, cleanup).run(receiver, Arc::clone(&status)));
+        Self {
+            guild_id,
+            sender,
+            status,
+            cleanup,
+            dj: DjPolicy::from_env(),
+        }
+    }
+
+    /// Channel to answer in; follows the guild's cleanup policy.
+    fn reply_channel(&self, ctx: &Context, channel_id: ChannelId) -> MessageChannel {
+        let mut reply_channel = MessageChannel::new(channel_id, ctx.http.clone());
+        reply_channel.set_status_lifetime(self.cleanup.status_lifetime);
+        reply_channel
+    }
+
+    fn send(&self, message: GuildMessage) {
+        self.status
+            .lock()
+            .expect("failed to unlock worker status")
+            .pending += 1;
+        if let Err(err) = self.sender.send(message) {
+            self.status
+                .lock()
+                .expect("failed to unlock worker status")
+                .pending -= 1;
+            eprintln!(
+                "worker of guild {} is gone; dropped `{}`",
+                self.guild_id,
+                err.0.name()
+            );
+        }
+    }
+
+    /// Sends a command to the worker and waits for its result.
+    ///
+    /// The command is skipped if the worker doesn't get to it within `COMMAND_TIMEOUT`.
+    async fn request(&self, issuer: UserId, command: GuildCommand) -> CommandResult {
+        self.request_step(issuer, next_command_id(), command).await
+    }
+
+    /// Like `request`, but for a single step of a command which is sent to the worker in several
+    /// steps; they all share the same `command_id`.
+    async fn request_step(
+        &self,
+        issuer: UserId,
+        command_id: CommandId,
+        command: GuildCommand,
+    ) -> CommandResult {
+        let name = command.name();
+        let (reply, result) = oneshot::channel();
+        self.send(GuildMessage::Command(command, issuer, command_id, reply));
+        match tokio::time::timeout(COMMAND_TIMEOUT, result).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(CommandError::Execution(format!(
+                "The `{name}` command was cancelled."
+            ))),
+            Err(_) => Err(CommandError::Execution(format!(
+                "The `{name}` command took too long and has been cancelled."
+            ))),
         }
     }
 
     pub async fn is_command_channel(&self, channel: ChannelId) -> bool {
-        self.state.lock().await.is_command_channel(channel)
+        let (reply, result) = oneshot::channel();
+        self.send(GuildMessage::IsCommandChannel(channel, reply));
+        result.await.unwrap_or(false)
+    }
+
+    /// Track references of one of the guild's shared playlists.
+    async fn shared_playlist_tracks(&self, name: String) -> CommandResult<TrackRefs> {
+        let (reply, result) = oneshot::channel();
+        self.send(GuildMessage::SharedPlaylistTracks(name, reply));
+        result.await.unwrap_or_else(|_| {
+            Err(CommandError::Execution(
+                "The shared playlists are not available right now.".to_owned(),
+            ))
+        })
+    }
+
+    /// Whether the given message is the latest now-playing panel.
+    pub async fn is_panel_message(&self, message_id: MessageId) -> bool {
+        let (reply, result) = oneshot::channel();
+        self.send(GuildMessage::IsPanelMessage(message_id, reply));
+        result.await.unwrap_or(false)
+    }
+
+    pub fn handle_track_end(&self) {
+        self.send(GuildMessage::TrackEnd);
+    }
+
+    pub fn handle_voice_state_update(&self, ctx: &Context, voice_state: &VoiceState) {
+        self.send(GuildMessage::VoiceStateUpdate {
+            ctx: ctx.clone(),
+            user_id: voice_state.user_id,
+            channel_id: voice_state.channel_id,
+        });
     }
 
-    pub async fn handle_track_end(&self) {
-        self.state.lock().await.queue.handle_track_end().await;
+    pub fn handle_driver_disconnect(&self, reason: Option<String>) {
+        self.send(GuildMessage::DriverDisconnect(reason));
     }
 
-    pub async fn command_pause(&self) -> CommandResult {
-        self.state.lock().await.queue.pause()
+    pub fn handle_driver_reconnect(&self) {
+        self.send(GuildMessage::DriverReconnect);
     }
 
-    pub async fn command_stop(&self) -> CommandResult {
-        self.state.lock().await.queue.stop()
+    pub async fn command_pause(&self, user_id: UserId) -> CommandResult {
+        self.request(user_id, GuildCommand::Pause).await
     }
 
-    pub async fn command_resume(&self) -> CommandResult {
-        self.state.lock().await.queue.resume()
+    pub async fn command_stop(&self, user_id: UserId) -> CommandResult {
+        self.request(user_id, GuildCommand::Stop).await
     }
 
-    pub async fn command_play(&self) -> CommandResult {
-        self.state.lock().await.queue.play().await
+    pub async fn command_resume(&self, user_id: UserId) -> CommandResult {
+        self.request(user_id, GuildCommand::Resume).await
+    }
+
+    pub async fn command_play(&self, user_id: UserId) -> CommandResult {
+        self.request(user_id, GuildCommand::Play).await
     }
 
     pub async fn command_print(
         &self,
         reply_channel: &MessageChannel,
-        tracks: &TrackIndexSelection,
+        tracks: TrackIndexSelection,
+        user_id: UserId,
     ) -> CommandResult {
-        self.state
-            .lock()
-            .await
-            .queue
-            .print(reply_channel, tracks)
-            .await
+        self.request(
+            user_id,
+            GuildCommand::Print {
+                out: reply_channel.clone(),
+                tracks,
+            },
+        )
+        .await
     }
 
-    pub async fn command_goto(&self, track_index: TrackIndex) -> CommandResult {
-        self.state.lock().await.queue.goto(track_index).await
+    pub async fn command_goto(&self, track_index: TrackIndex, user_id: UserId) -> CommandResult {
+        self.request(user_id, GuildCommand::Goto(track_index)).await
     }
 
-    pub async fn command_next(&self) -> CommandResult {
-        self.state.lock().await.queue.next().await
+    pub async fn command_next(&self, user_id: UserId) -> CommandResult {
+        self.request(user_id, GuildCommand::Next).await
     }
 
-    pub async fn command_prev(&self) -> CommandResult {
-        self.state.lock().await.queue.prev().await
+    pub async fn command_prev(&self, user_id: UserId) -> CommandResult {
+        self.request(user_id, GuildCommand::Prev).await
     }
 
     pub async fn command_remove(
         &self,
         reply_channel: &MessageChannel,
-        tracks: &TrackIndexSelection,
+        tracks: TrackIndexSelection,
+        user_id: UserId,
     ) -> CommandResult {
-        self.state
-            .lock()
-            .await
-            .queue
-            .remove(reply_channel, tracks)
-            .await
+        self.request(
+            user_id,
+            GuildCommand::Remove {
+                out: reply_channel.clone(),
+                tracks,
+            },
+        )
+        .await
     }
 
     async fn command_join(
@@ -301,100 +985,403 @@ impl GuildStateHandle {
         reply_channel: &MessageChannel,
         ctx: &Context,
         msg: &Message,
+        channel: Option<String>,
+        is_dj: bool,
     ) -> CommandResult {
-        self.state.lock().await.join(reply_channel, ctx, msg).await
+        self.request(
+            msg.author.id,
+            GuildCommand::Join {
+                out: reply_channel.clone(),
+                ctx: ctx.clone(),
+                msg: Box::new(msg.clone()),
+                channel,
+                is_dj,
+            },
+        )
+        .await
     }
 
-    async fn command_leave(&self, reply_channel: &MessageChannel, ctx: &Context) -> CommandResult {
-        self.state.lock().await.leave(reply_channel, ctx).await
+    async fn command_leave(
+        &self,
+        reply_channel: &MessageChannel,
+        ctx: &Context,
+        user_id: UserId,
+    ) -> CommandResult {
+        self.request(
+            user_id,
+            GuildCommand::Leave {
+                out: reply_channel.clone(),
+                ctx: ctx.clone(),
+            },
+        )
+        .await
     }
 
+    /// Resolves the given track references and appends them to the queue.
+    ///
+    /// Resolving tracks may take a while, so this happens outside of the guild's worker which
+    /// will only be asked to append each resolved track. Other commands remain responsive in the
+    /// meantime.
     async fn command_enqueue(
         &self,
         reply_channel: &MessageChannel,
         track_refs: Vec<(String, Option<String>)>,
         user: &User,
     ) -> CommandResult {
-        self.state
-            .lock()
-            .await
-            .enqueue(reply_channel, track_refs, user)
-            .await
+        if track_refs.is_empty() {
+            return Err(CommandError::Usage {
+                message: "Please specify an URL or another locator for the track to enqeue.\ne.g. `enqueue https://example.com/path/to/track`".to_owned(),
+                topic: HelpTopic::Enqueue,
+            });
+        }
+
+        let mut success = false;
+        let mut errors = String::new();
+        // all tracks are undone at once
+        let command_id = next_command_id();
+
+        for (track_ref, comment) in track_refs {
+            if let Some(mut maybe_tracks) = track::dispatch(&track_ref, comment, user).await {
+                // append tracks as they arrive, so that playback can start early
+                while let Some(track) = maybe_tracks.next().await {
+                    match track {
+                        Ok(track) => {
+                            self.request_step(user.id, command_id, GuildCommand::Append(track))
+                                .await?;
+                            success = true;
+                        }
+                        Err(err) => {
+                            writeln!(errors, "{err}").unwrap();
+                        }
+                    }
+                }
+            } else {
+                reply_channel.print(format!("Failed to interpret `{track_ref}` as a track reference.\nMight be from an unsupported provider.")).await;
+            }
+        }
+
+        if success {
+            Ok(())
+        } else {
+            Err(CommandError::Execution(errors))
+        }
     }
 
-    async fn command_seek(&self, position: Duration) -> CommandResult {
-        self.state.lock().await.queue.seek(position)
+    /// Unlike `command_enqueue` this waits for all tracks, so they end up next to each other.
+    async fn command_insert(
+        &self,
+        reply_channel: &MessageChannel,
+        track_refs: Vec<(String, Option<String>)>,
+        user: &User,
+        index: TrackIndex,
+        is_dj: bool,
+    ) -> CommandResult {
+        if track_refs.is_empty() {
+            return Err(CommandError::Usage {
+                message: "Please specify an URL or another locator for the track to insert.\ne.g. `+next https://example.com/path/to/track`".to_owned(),
+                topic: HelpTopic::EnqueueAt,
+            });
+        }
+
+        let mut tracks = Vec::new();
+        let mut errors = String::new();
+
+        for (track_ref, comment) in track_refs {
+            if let Some(maybe_tracks) = track::dispatch(&track_ref, comment, user).await {
+                for track in maybe_tracks.collect::<Vec<_>>().await {
+                    match track {
+                        Ok(track) => tracks.push(track),
+                        Err(err) => {
+                            writeln!(errors, "{err}").unwrap();
+                        }
+                    }
+                }
+            } else {
+                reply_channel.print(format!("Failed to interpret `{track_ref}` as a track reference.\nMight be from an unsupported provider.")).await;
+            }
+        }
+
+        if tracks.is_empty() {
+            return Err(CommandError::Execution(errors));
+        }
+        if !errors.is_empty() {
+            reply_channel.print(errors).await;
+        }
+        self.request(
+            user.id,
+            GuildCommand::Insert {
+                out: reply_channel.clone(),
+                tracks,
+                index,
+                is_dj,
+            },
+        )
+        .await
+    }
+
+    async fn command_seek(&self, position: Duration, user_id: UserId) -> CommandResult {
+        self.request(user_id, GuildCommand::Seek(position)).await
+    }
+
+    pub async fn command_loop(
+        &self,
+        reply_channel: &MessageChannel,
+        user_id: UserId,
+    ) -> CommandResult {
+        self.request(
+            user_id,
+            GuildCommand::Loop {
+                out: reply_channel.clone(),
+            },
+        )
+        .await
     }
 
-    pub async fn command_now(&self, reply_channel: &MessageChannel) -> CommandResult {
-        self.state.lock().await.queue.now(reply_channel).await
+    pub async fn command_now(
+        &self,
+        reply_channel: &MessageChannel,
+        user_id: UserId,
+    ) -> CommandResult {
+        self.request(
+            user_id,
+            GuildCommand::Now {
+                out: reply_channel.clone(),
+            },
+        )
+        .await
     }
 
-    pub async fn command_reverse(&self, tracks: &TrackIndexSelection) -> CommandResult {
-        self.state.lock().await.queue.reverse(tracks).await
+    pub async fn command_info(
+        &self,
+        reply_channel: &MessageChannel,
+        track_index: TrackIndex,
+        user_id: UserId,
+    ) -> CommandResult {
+        self.request(
+            user_id,
+            GuildCommand::Info {
+                out: reply_channel.clone(),
+                index: track_index,
+            },
+        )
+        .await
     }
 
-    pub async fn command_set_quota(&self, quota: Option<usize>) -> CommandResult {
-        self.state.lock().await.queue.set_quota(quota)
+    pub async fn command_reverse(
+        &self,
+        tracks: TrackIndexSelection,
+        user_id: UserId,
+    ) -> CommandResult {
+        self.request(user_id, GuildCommand::Reverse(tracks)).await
     }
 
-    pub async fn command_print_quota(&self, reply_channel: &MessageChannel) -> CommandResult {
-        self.state
-            .lock()
-            .await
-            .queue
-            .print_quota(reply_channel)
-            .await
+    pub async fn command_set_quota(&self, quota: Option<usize>, user_id: UserId) -> CommandResult {
+        self.request(user_id, GuildCommand::SetQuota(quota)).await
+    }
+
+    pub async fn command_print_quota(
+        &self,
+        reply_channel: &MessageChannel,
+        user_id: UserId,
+    ) -> CommandResult {
+        self.request(
+            user_id,
+            GuildCommand::PrintQuota {
+                out: reply_channel.clone(),
+            },
+        )
+        .await
     }
 
     pub async fn command_move(
         &self,
         reply_channel: &MessageChannel,
-        tracks: &TrackIndexSelection,
+        tracks: TrackIndexSelection,
         index: TrackIndex,
+        user_id: UserId,
     ) -> CommandResult {
-        self.state
-            .lock()
-            .await
-            .queue
-            .move_tracks(reply_channel, tracks, index)
-            .await
+        self.request(
+            user_id,
+            GuildCommand::Move {
+                out: reply_channel.clone(),
+                tracks,
+                index,
+            },
+        )
+        .await
     }
 
     pub async fn command_when(
         &self,
         reply_channel: &MessageChannel,
         track_index: TrackIndex,
+        user_id: UserId,
+    ) -> CommandResult {
+        self.request(
+            user_id,
+            GuildCommand::When {
+                out: reply_channel.clone(),
+                index: track_index,
+            },
+        )
+        .await
+    }
+
+    pub async fn command_history(
+        &self,
+        reply_channel: &MessageChannel,
+        count: usize,
+        user_id: UserId,
+    ) -> CommandResult {
+        self.request(
+            user_id,
+            GuildCommand::History {
+                out: reply_channel.clone(),
+                count,
+            },
+        )
+        .await
+    }
+
+    pub async fn command_stats(
+        &self,
+        reply_channel: &MessageChannel,
+        category: Option<StatsCategory>,
+        period: StatsPeriod,
+        user_id: UserId,
     ) -> CommandResult {
-        self.state
+        self.request(
+            user_id,
+            GuildCommand::Stats {
+                out: reply_channel.clone(),
+                category,
+                period,
+            },
+        )
+        .await
+    }
+
+    async fn command_playlist(
+        &self,
+        reply_channel: &MessageChannel,
+        command: PlaylistCommand,
+        user: &User,
+    ) -> CommandResult {
+        match command {
+            PlaylistCommand::Save { name, tracks } => {
+                self.request(
+                    user.id,
+                    GuildCommand::SavePlaylist {
+                        out: reply_channel.clone(),
+                        user_id: user.id,
+                        name,
+                        tracks,
+                    },
+                )
+                .await
+            }
+            PlaylistCommand::Load(name) => {
+                let track_refs = PlaylistStore::open_personal(user.id)
+                    .await?
+                    .find(&name)?
+                    .track_refs();
+                if track_refs.is_empty() {
+                    return Err(CommandError::Execution(format!(
+                        "The playlist `{name}` is empty."
+                    )));
+                }
+                self.command_enqueue(reply_channel, track_refs, user).await
+            }
+            PlaylistCommand::List => {
+                match PlaylistStore::open_personal(user.id).await?.describe() {
+                    None => {
+                        reply_channel
+                            .print(format!(
+                                "You haven't saved any playlists yet. Use `{CMD_PLAYLIST} save <name>` to save the current queue."
+                            ))
+                            .await;
+                    }
+                    Some(listing) => {
+                        reply_channel
+                            .print(format!("Your playlists:\n{listing}"))
+                            .await;
+                    }
+                }
+                Ok(())
+            }
+            PlaylistCommand::Delete(name) => {
+                let mut store = PlaylistStore::open_personal(user.id).await?;
+                let playlist = store.remove(&name)?;
+                store.save().await?;
+                reply_channel
+                    .print_status(format!("Deleted the playlist `{}`.", playlist.name))
+                    .await;
+                Ok(())
+            }
+        }
+    }
+
+    pub async fn command_undo(
+        &self,
+        reply_channel: &MessageChannel,
+        user_id: UserId,
+    ) -> CommandResult {
+        self.request(
+            user_id,
+            GuildCommand::Undo {
+                out: reply_channel.clone(),
+            },
+        )
+        .await
+    }
+
+    pub async fn command_redo(
+        &self,
+        reply_channel: &MessageChannel,
+        user_id: UserId,
+    ) -> CommandResult {
+        self.request(
+            user_id,
+            GuildCommand::Redo {
+                out: reply_channel.clone(),
+            },
+        )
+        .await
+    }
+
+    pub async fn command_status(&self, reply_channel: &MessageChannel) -> CommandResult {
+        let worker_status = self
+            .status
             .lock()
-            .await
-            .queue
-            .when(reply_channel, track_index)
-            .await
+            .expect("failed to unlock worker status")
+            .to_string();
+        reply_channel
+            .print(format!(
+                "{}\n{worker_status}",
+                AUDIOTOOL_HTTP_CLIENT.status()
+            ))
+            .await;
+        Ok(())
     }
 
-    pub async fn print_default(&self, message: impl fmt::Display) {
+    pub fn print_default(&self, message: impl fmt::Display) {
         println!("print {message}");
-        self.state.lock().await.print(message).await;
+        self.send(GuildMessage::Print(message.to_string()));
     }
 
-    pub async fn set_http(&self, http: Arc<Http>) {
+    pub fn set_http(&self, http: Arc<Http>) {
         println!("set_http");
-        self.state.lock().await.set_http(http);
+        self.send(GuildMessage::SetHttp(http));
     }
 
-    pub async fn set_default_output_channel(&self, channel_id: ChannelId) {
+    pub fn set_default_output_channel(&self, channel_id: ChannelId) {
         println!("set_default_output_channel");
-        self.state
-            .lock()
-            .await
-            .set_default_output_channel(channel_id);
+        self.send(GuildMessage::SetDefaultOutputChannel(channel_id));
     }
 
-    pub async fn add_command_channel(&self, channel_id: ChannelId) {
+    pub fn add_command_channel(&self, channel_id: ChannelId) {
         println!("add_command_channel");
-        self.state.lock().await.add_command_channel(channel_id);
+        self.send(GuildMessage::AddCommandChannel(channel_id));
     }
 }
 
@@ -403,16 +1390,257 @@ struct GuildState {
     queue: Queue,
     default_reply_channel: MessageChannel,
     command_channels: HashSet<ChannelId>,
+    /// opened on first use
+    shared_playlists: Option<PlaylistStore>,
+    /// voice manager which has been used to join the current voice channel
+    songbird: Option<Arc<Songbird>>,
+    voice_channel: Option<ChannelId>,
+    auto_leave: AutoLeavePolicy,
+    /// since when there are no listeners left in the bot's voice channel
+    alone_since: Option<Instant>,
+    /// since when the bot is connected without playing anything
+    idle_since: Option<Instant>,
+    /// when the progress shown by the now-playing panel should be updated next
+    panel_refresh_at: Option<Instant>,
 }
 
 impl GuildState {
-    pub fn new(id: GuildId) -> Self {
-        let default_reply_channel = MessageChannel::default();
+    fn new(id: GuildId, cleanup: CleanupPolicy) -> Self {
+        let mut default_reply_channel = MessageChannel::default();
+        default_reply_channel.set_status_lifetime(cleanup.status_lifetime);
         Self {
             id,
             queue: Queue::new(id, default_reply_channel.clone()),
             default_reply_channel,
             command_channels: HashSet::new(),
+            shared_playlists: None,
+            songbird: None,
+            voice_channel: None,
+            auto_leave: AutoLeavePolicy::from_env(),
+            alone_since: None,
+            idle_since: None,
+            panel_refresh_at: None,
+        }
+    }
+
+    /// Processes the guild's messages until all handles are gone.
+    async fn run(
+        mut self,
+        mut receiver: mpsc::UnboundedReceiver<GuildMessage>,
+        status: Arc<StdMutex<WorkerStatus>>,
+    ) {
+        self.queue.load_play_history().await;
+        loop {
+            let deadline = self.auto_leave_deadline();
+            let message = tokio::select! {
+                message = receiver.recv() => match message {
+                    Some(message) => message,
+                    None => break,
+                },
+                () = sleep_until(deadline) => {
+                    self.handle_auto_leave_deadline().await;
+                    continue;
+                }
+                () = sleep_until(self.panel_refresh_at) => {
+                    self.queue.refresh_panel().await;
+                    self.panel_refresh_at = Some(Instant::now() + PANEL_REFRESH_INTERVAL);
+                    continue;
+                }
+            };
+            {
+                let mut status = status.lock().expect("failed to unlock worker status");
+                status.pending -= 1;
+                status.busy_with = Some((message.name(), Instant::now()));
+            }
+            let name = message.name();
+            let affects_playback = message.affects_playback();
+            // a bug within a single command must not take down the whole guild; the issuer
+            // learns about it as the reply gets dropped
+            let completed = AssertUnwindSafe(self.handle_message(message))
+                .catch_unwind()
+                .await
+                .unwrap_or_else(|_| {
+                    eprintln!(
+                        "worker of guild {} panicked while handling `{name}`",
+                        self.id
+                    );
+                    self.queue.set_acting_user(None);
+                    self.queue.set_acting_command(None);
+                    false
+                });
+            if affects_playback {
+                self.update_idle_state().await;
+            }
+            {
+                let mut status = status.lock().expect("failed to unlock worker status");
+                status.busy_with = None;
+                status.processed += 1;
+                if !completed {
+                    status.cancelled += 1;
+                }
+            }
+        }
+        println!("worker of guild {} stopped", self.id);
+    }
+
+    /// Returns `false` if the message has been cancelled by its sender.
+    ///
+    /// Only commands which haven't started yet can be cancelled; a command which got interrupted
+    /// midway might leave the queue half-changed. Resolving tracks over the network is what
+    /// usually takes long, but that's done by the issuer before the command is sent.
+    async fn handle_message(&mut self, message: GuildMessage) -> bool {
+        match message {
+            GuildMessage::Command(command, issuer, command_id, reply) => {
+                if reply.is_closed() {
+                    println!("skipping cancelled command `{}`", command.name());
+                    return false;
+                }
+                self.queue.set_acting_user(Some(issuer));
+                self.queue.set_acting_command(Some(command_id));
+                let result = self.execute(command).await;
+                self.queue.set_acting_user(None);
+                self.queue.set_acting_command(None);
+                self.queue.refresh_panel().await;
+                let _ = reply.send(result);
+                true
+            }
+            GuildMessage::IsCommandChannel(channel, reply) => {
+                let _ = reply.send(self.is_command_channel(channel));
+                true
+            }
+            GuildMessage::SharedPlaylistTracks(name, reply) => {
+                let _ = reply.send(self.shared_playlist_tracks(&name).await);
+                true
+            }
+            GuildMessage::IsPanelMessage(message_id, reply) => {
+                let _ = reply.send(self.queue.panel_message_id() == Some(message_id));
+                true
+            }
+            GuildMessage::TrackEnd => {
+                self.queue.handle_track_end().await;
+                self.queue.refresh_panel().await;
+                true
+            }
+            GuildMessage::VoiceStateUpdate {
+                ctx,
+                user_id,
+                channel_id,
+            } => {
+                if user_id == ctx.cache.current_user_id() {
+                    self.handle_own_voice_state(channel_id).await;
+                }
+                self.handle_voice_state_update(&ctx).await;
+                self.queue.refresh_panel().await;
+                true
+            }
+            GuildMessage::DriverDisconnect(reason) => {
+                self.handle_driver_disconnect(reason).await;
+                true
+            }
+            GuildMessage::DriverReconnect => {
+                if let Err(err) = self.queue.restore_playback().await {
+                    eprintln!("failed to restore playback after reconnect: {err}");
+                }
+                self.queue.refresh_panel().await;
+                true
+            }
+            GuildMessage::Print(message) => {
+                self.print(message).await;
+                true
+            }
+            GuildMessage::SetHttp(http) => {
+                self.set_http(http);
+                true
+            }
+            GuildMessage::SetDefaultOutputChannel(channel_id) => {
+                self.set_default_output_channel(channel_id);
+                true
+            }
+            GuildMessage::AddCommandChannel(channel_id) => {
+                self.add_command_channel(channel_id);
+                true
+            }
+        }
+    }
+
+    async fn execute(&mut self, command: GuildCommand) -> CommandResult {
+        match command {
+            GuildCommand::Join {
+                out,
+                ctx,
+                msg,
+                channel,
+                is_dj,
+            } => self.join(&out, &ctx, &msg, channel.as_deref(), is_dj).await,
+            GuildCommand::Leave { out, ctx } => {
+                let songbird = songbird::get(&ctx)
+                    .await
+                    .expect("Songbird Voice client placed in at initialisation.");
+                self.leave(&out, &songbird).await
+            }
+            GuildCommand::Append(track) => {
+                self.queue.append(track).await;
+                Ok(())
+            }
+            GuildCommand::Insert {
+                out,
+                tracks,
+                index,
+                is_dj,
+            } => self.queue.insert(&out, tracks, index, is_dj).await,
+            GuildCommand::Pause => self.queue.pause(PauseReason::User),
+            GuildCommand::Resume => self.queue.resume(),
+            GuildCommand::Play => self.queue.play().await,
+            GuildCommand::Stop => self.queue.stop(),
+            GuildCommand::Print { out, tracks } => self.queue.print(&out, &tracks).await,
+            GuildCommand::TurnQueuePage {
+                http,
+                component,
+                start,
+            } => {
+                self.queue
+                    .queue_page_at(start)
+                    .replace(&http, &component)
+                    .await
+            }
+            GuildCommand::Goto(index) => self.queue.goto(index).await,
+            GuildCommand::Next => self.queue.next().await,
+            GuildCommand::Prev => self.queue.prev().await,
+            GuildCommand::Remove { out, tracks } => self.queue.remove(&out, &tracks).await,
+            GuildCommand::Seek(position) => self.queue.seek(position),
+            GuildCommand::Loop { out } => self.queue.toggle_loop(&out).await,
+            GuildCommand::Now { out } => self.queue.now(&out).await,
+            GuildCommand::Info { out, index } => self.queue.info(&out, index).await,
+            GuildCommand::Reverse(tracks) => self.queue.reverse(&tracks).await,
+            GuildCommand::SetQuota(quota) => self.queue.set_quota(quota),
+            GuildCommand::PrintQuota { out } => self.queue.print_quota(&out).await,
+            GuildCommand::Move { out, tracks, index } => {
+                self.queue.move_tracks(&out, &tracks, index).await
+            }
+            GuildCommand::When { out, index } => self.queue.when(&out, index).await,
+            GuildCommand::History { out, count } => self.queue.history(&out, count).await,
+            GuildCommand::Stats {
+                out,
+                category,
+                period,
+            } => self.queue.stats(&out, category, period).await,
+            GuildCommand::SavePlaylist {
+                out,
+                user_id,
+                name,
+                tracks,
+            } => self.queue.save_playlist(&out, user_id, name, &tracks).await,
+            GuildCommand::SharedPlaylist {
+                out,
+                user_id,
+                is_dj,
+                command,
+            } => {
+                self.execute_shared_playlist(&out, user_id, is_dj, command)
+                    .await
+            }
+            GuildCommand::Undo { out } => self.queue.undo(&out).await,
+            GuildCommand::Redo { out } => self.queue.redo(&out).await,
         }
     }
 
@@ -420,6 +1648,122 @@ impl GuildState {
         self.command_channels.contains(&channel)
     }
 
+    /// The guild's shared playlists; a broken store won't be replaced until it has been fixed.
+    async fn shared_playlists(&mut self) -> CommandResult<&mut PlaylistStore> {
+        let store = match self.shared_playlists.take() {
+            Some(store) => store,
+            None => PlaylistStore::open_shared(self.id).await?,
+        };
+        Ok(self.shared_playlists.get_or_insert(store))
+    }
+
+    /// Track references of one of the guild's shared playlists; to be enqueued by the issuer.
+    async fn shared_playlist_tracks(&mut self, name: &str) -> CommandResult<TrackRefs> {
+        let track_refs = self.shared_playlists().await?.find(name)?.track_refs();
+        if track_refs.is_empty() {
+            return Err(CommandError::Execution(format!(
+                "The playlist `{name}` is empty."
+            )));
+        }
+        Ok(track_refs)
+    }
+
+    async fn execute_shared_playlist(
+        &mut self,
+        out: &MessageChannel,
+        user_id: UserId,
+        is_dj: bool,
+        command: SharedPlaylistCommand,
+    ) -> CommandResult {
+        let may_edit = |playlist: &Playlist| {
+            if is_dj || playlist.owner == Some(user_id) {
+                Ok(())
+            } else {
+                Err(CommandError::Execution(format!(
+                    "Only the creator of the playlist `{}` and DJs are allowed to change it.",
+                    playlist.name
+                )))
+            }
+        };
+
+        match command {
+            SharedPlaylistCommand::Create { name, tracks } => {
+                let entries = if tracks.is_empty() {
+                    Vec::new()
+                } else {
+                    self.queue.playlist_entries(&tracks)?
+                };
+                let store = self.shared_playlists().await?;
+                if store.find(&name).is_ok() {
+                    return Err(CommandError::Execution(format!(
+                        "There's already a playlist named `{name}`."
+                    )));
+                }
+                let track_count = entries.len();
+                store.put(Playlist {
+                    name: name.clone(),
+                    owner: Some(user_id),
+                    entries,
+                });
+                store.save().await?;
+                out.print_status(format!(
+                    "Created the shared playlist `{name}` with {track_count} track(s)."
+                ))
+                .await;
+            }
+            SharedPlaylistCommand::Add { name, tracks } => {
+                let entries = self.queue.playlist_entries(&tracks)?;
+                let track_count = entries.len();
+                let store = self.shared_playlists().await?;
+                let playlist = store.find_mut(&name)?;
+                may_edit(playlist)?;
+                playlist.entries.extend(entries);
+                let name = playlist.name.clone();
+                store.save().await?;
+                out.print_status(format!(
+                    "Added {track_count} track(s) to the shared playlist `{name}`."
+                ))
+                .await;
+            }
+            SharedPlaylistCommand::Move { name, from, to } => {
+                let store = self.shared_playlists().await?;
+                let playlist = store.find_mut(&name)?;
+                may_edit(playlist)?;
+                playlist.move_entry(from, to)?;
+                let name = playlist.name.clone();
+                store.save().await?;
+                out.print_status(format!(
+                    "Moved track #{} of the shared playlist `{name}` to position #{}.",
+                    from + 1,
+                    to + 1
+                ))
+                .await;
+            }
+            SharedPlaylistCommand::Show(name) => {
+                let message = describe_shared_playlist(self.shared_playlists().await?.find(&name)?);
+                out.print(message).await;
+            }
+            SharedPlaylistCommand::List => match self.shared_playlists().await?.describe() {
+                None => {
+                    out.print(format!(
+                            "This server doesn't have any shared playlists yet. Use `{CMD_SHARED_PLAYLIST} create <name>` to create one."
+                        ))
+                        .await;
+                }
+                Some(listing) => out.print(format!("Shared playlists:\n{listing}")).await,
+            },
+            SharedPlaylistCommand::Delete(name) => {
+                let store = self.shared_playlists().await?;
+                may_edit(store.find(&name)?)?;
+                let playlist = store.remove(&name)?;
+                store.save().await?;
+                out.print_status(format!("Deleted the shared playlist `{}`.", playlist.name))
+                    .await;
+            }
+        }
+        Ok(())
+    }
+
     pub async fn print(&mut self, message: impl fmt::Display) {
         self.default_reply_channel.print(message).await;
     }
@@ -438,21 +1782,47 @@ impl GuildState {
         self.command_channels.insert(channel_id);
     }
 
-    async fn join(&mut self, out: &MessageChannel, ctx: &Context, msg: &Message) -> CommandResult {
+    async fn join(
+        &mut self,
+        out: &MessageChannel,
+        ctx: &Context,
+        msg: &Message,
+        channel: Option<&str>,
+        is_dj: bool,
+    ) -> CommandResult {
         let guild = msg
             .guild(&ctx.cache)
             .ok_or_else(|| CommandError::Discord("Could not retrieve guild".to_owned()))?;
 
-        let channel_id = guild
-            .voice_states
-            .get(&msg.author.id)
-            .and_then(|voice_state| voice_state.channel_id);
+        let connect_to = if let Some(channel) = channel {
+            find_voice_channel(&guild, channel).map_err(CommandError::Execution)?
+        } else {
+            let channel_id = guild
+                .voice_states
+                .get(&msg.author.id)
+                .and_then(|voice_state| voice_state.channel_id);
 
-        let Some(connect_to) = channel_id else {
-            out.print("Not in a voice channel").await;
-            return Err(CommandError::UserVoiceChannelRequired);
+            let Some(channel_id) = channel_id else {
+                out.print("Not in a voice channel").await;
+                return Err(CommandError::UserVoiceChannelRequired);
+            };
+            channel_id
         };
 
+        let move_from = self.voice_channel.filter(|_| self.queue.is_connected());
+        if move_from == Some(connect_to) {
+            return Err(CommandError::Execution(format!(
+                "I'm already in <#{connect_to}>."
+            )));
+        }
+        // nobody must be able to pull the bot away from its listeners
+        let has_listeners = move_from.is_some() && count_listeners(ctx, self.id) != Some(0);
+        if !is_dj && (channel.is_some() || has_listeners) {
+            return Err(CommandError::Execution(
+                "Only DJs are allowed to send me to another voice channel or to move me while others are listening.".to_owned(),
+            ));
+        }
+
         let songbird = songbird::get(ctx)
             .await
             .expect("Songbird Voice client placed in at initialisation.")
@@ -460,26 +1830,44 @@ impl GuildState {
 
         let (voice_connection, _) = songbird.join(self.id, connect_to).await;
 
-        self.queue.connect(voice_connection);
+        self.songbird = Some(songbird);
+        self.voice_channel = Some(connect_to);
+        self.queue.connect(voice_connection).await;
         let _ = self.queue.deafen().await;
 
+        let stage = match guild.channels.get(&connect_to) {
+            Some(Channel::Guild(channel)) if channel.kind == ChannelType::Stage => {
+                Some(Stage::new(channel.clone(), ctx.http.clone()))
+            }
+            _ => None,
+        };
+        if let Some(stage) = &stage {
+            stage.become_speaker().await;
+        }
+        self.queue.set_stage(stage).await;
+
         // this is a public announcement and not a direct reply to the issuer
-        self.print("Hello my friends! Stay a while and listen!")
+        if let Some(move_from) = move_from {
+            self.print(format!(
+                "Moving over from <#{move_from}> to <#{connect_to}>. The playback continues there."
+            ))
             .await;
+        } else {
+            self.print("Hello my friends! Stay a while and listen!")
+                .await;
+        }
 
         Ok(())
     }
 
     // TODO move into wrapped guild
-    async fn leave(&mut self, out: &MessageChannel, ctx: &Context) -> CommandResult {
+    async fn leave(&mut self, out: &MessageChannel, manager: &Songbird) -> CommandResult {
         println!("command: leave");
 
-        let manager = songbird::get(ctx)
-            .await
-            .expect("Songbird Voice client placed in at initialisation.")
-            .clone();
         let has_handler = manager.get(self.id).is_some();
 
+        self.queue.set_stage(None).await;
+
         if has_handler {
             if let Err(e) = manager.remove(self.id).await {
                 out.print(format!("Failed: {e:?}")).await;
@@ -489,67 +1877,332 @@ impl GuildState {
         }
 
         self.queue.disconnect();
+        self.songbird = None;
+        self.voice_channel = None;
+        self.alone_since = None;
+        self.idle_since = None;
 
         Ok(())
     }
 
-    async fn enqueue(
-        &mut self,
-        out: &MessageChannel,
-        track_refs: Vec<(String, Option<String>)>,
-        user: &User,
-    ) -> CommandResult {
-        if track_refs.is_empty() {
-            return Err(CommandError::Usage {
-                message: "Please specify an URL or another locator for the track to enqeue.\ne.g. `enqueue https://example.com/path/to/track`".to_owned(),
-                topic: HelpTopic::Enqueue,
-            });
+    /// Follows the bot when it has been moved to another voice channel or disconnected by someone
+    /// else.
+    async fn handle_own_voice_state(&mut self, channel_id: Option<ChannelId>) {
+        if !self.queue.is_connected() || channel_id == self.voice_channel {
+            return;
         }
 
-        let mut tracks = Vec::new();
+        if let Some(channel_id) = channel_id {
+            // songbird moves the call by itself
+            println!("guild {}: moved to voice channel {channel_id}", self.id);
+            self.voice_channel = Some(channel_id);
+        } else {
+            println!("guild {}: disconnected from voice by someone else", self.id);
+            let Some(songbird) = self.songbird.clone() else {
+                return;
+            };
+            let out = self.default_reply_channel.clone();
+            let _ = self.leave(&out, &songbird).await;
+            self.print(format!(
+                "Somebody disconnected me from the voice channel. Use the `{CMD_JOIN}` command to invite me again."
+            ))
+            .await;
+        }
+    }
 
-        let dispatchers: Vec<Box<dyn TrackRefDispatcher>> = vec![
-            Box::new(AudiotoolTrackRefDispatcher {}),
-            Box::new(AudiotoolSingleChartsDispatcher {}),
-            Box::new(AudiotoolGenreChartsDispatcher {}),
-            Box::new(AudiotoolAlbumDispatcher {}),
-            Box::new(YoutubeTrackRefDispatcher {}),
-        ];
+    /// Rejoins the voice channel after the connection broke down and continues where the
+    /// playback stopped.
+    async fn handle_driver_disconnect(&mut self, reason: Option<String>) {
+        if !self.queue.is_connected() {
+            return;
+        }
+        self.queue.save_position().await;
 
-        let mut errors = String::new();
+        // requested disconnects are either handled by `leave` or by the voice state update
+        let Some(reason) = reason else {
+            return;
+        };
+        let (Some(songbird), Some(channel_id)) = (self.songbird.clone(), self.voice_channel) else {
+            return;
+        };
+
+        eprintln!(
+            "guild {}: lost voice connection ({reason}); reconnecting",
+            self.id
+        );
+        let (voice_connection, result) = songbird.join(self.id, channel_id).await;
+        match result {
+            Ok(()) => {
+                self.queue.connect(voice_connection).await;
+                let _ = self.queue.deafen().await;
+                if let Some(stage) = self.queue.stage() {
+                    stage.become_speaker().await;
+                }
+                if let Err(err) = self.queue.restore_playback().await {
+                    eprintln!("failed to restore playback after reconnect: {err}");
+                }
+            }
+            Err(err) => {
+                eprintln!("guild {}: failed to reconnect: {err}", self.id);
+                let out = self.default_reply_channel.clone();
+                let _ = self.leave(&out, &songbird).await;
+                self.print(format!(
+                    "I lost the connection to the voice channel and couldn't get it back. Use the `{CMD_JOIN}` command to invite me again."
+                ))
+                .await;
+            }
+        }
+    }
+
+    /// Pauses the playback once the last listener left the bot's voice channel.
+    async fn handle_voice_state_update(&mut self, ctx: &Context) {
+        if !self.queue.is_connected() {
+            return;
+        }
 
-        'next_track_ref: for (track_ref, comment) in track_refs {
-            for dispatcher in &dispatchers {
-                if let Some(maybe_tracks) =
-                    dispatcher.dispatch(&track_ref, comment.clone(), user).await
+        let listeners = count_listeners(ctx, self.id);
+        self.queue.set_listener_count(listeners.unwrap_or_default());
+        match listeners {
+            Some(0) => {
+                if self.alone_since.is_some() {
+                    return;
+                }
+                println!("guild {}: no listeners left", self.id);
+                self.alone_since = Some(Instant::now());
+                if self.queue.is_playing().await
+                    && self.queue.pause(PauseReason::NoListeners).is_ok()
                 {
-                    for track in maybe_tracks {
-                        match track {
-                            Ok(track) => {
-                                tracks.push(track);
-                            }
-                            Err(err) => {
-                                writeln!(errors, "{err}").unwrap();
-                            }
-                        }
-                    }
-                    continue 'next_track_ref;
+                    let message = match self.auto_leave.alone_timeout {
+                        Some(timeout) => format!(
+                            "Everybody left, so I paused the playback. It will resume as soon as somebody joins again; otherwise I'll leave the voice channel in {} minute(s).",
+                            timeout.as_secs().div_ceil(60)
+                        ),
+                        None => "Everybody left, so I paused the playback. It will resume as soon as somebody joins again.".to_owned(),
+                    };
+                    self.print(message).await;
+                }
+            }
+            Some(_) => {
+                let was_alone = self.alone_since.take().is_some();
+                if was_alone && self.queue.resume_paused_by(PauseReason::NoListeners) {
+                    self.default_reply_channel
+                        .print_status("Welcome back! Resuming the playback.")
+                        .await;
                 }
             }
+            // we're not in a voice channel according to discord
+            None => {}
+        }
+    }
 
-            out.print(format!("Failed to interpret `{track_ref}` as a track reference.\nMight be from an unsupported provider.")).await;
+    /// Keeps track of how long the bot has been connected without playing anything; the progress
+    /// shown by the now-playing panel only needs to be updated while playing.
+    async fn update_idle_state(&mut self) {
+        let is_playing = self.queue.is_playing().await;
+        if self.queue.is_connected() && !is_playing {
+            self.idle_since.get_or_insert_with(Instant::now);
+        } else {
+            self.idle_since = None;
         }
+        if is_playing {
+            self.panel_refresh_at
+                .get_or_insert_with(|| Instant::now() + PANEL_REFRESH_INTERVAL);
+        } else {
+            self.panel_refresh_at = None;
+        }
+    }
 
-        let success = !tracks.is_empty();
+    /// The next time the bot should check whether to leave its voice channel.
+    fn auto_leave_deadline(&self) -> Option<Instant> {
+        let alone = self
+            .alone_since
+            .zip(self.auto_leave.alone_timeout)
+            .map(|(since, timeout)| since + timeout);
+        let idle = self
+            .idle_since
+            .zip(self.auto_leave.idle_timeout)
+            .map(|(since, timeout)| since + timeout);
+        alone.into_iter().chain(idle).min()
+    }
 
-        for user_track in tracks {
-            self.queue.append(user_track).await;
+    async fn handle_auto_leave_deadline(&mut self) {
+        let now = Instant::now();
+        match self.auto_leave_deadline() {
+            Some(deadline) if deadline <= now => {}
+            _ => return,
         }
 
-        if success {
-            Ok(())
+        let reason = if self.alone_since.is_some_and(|since| {
+            self.auto_leave
+                .alone_timeout
+                .is_some_and(|timeout| since + timeout <= now)
+        }) {
+            "Nobody has been listening for a while, so I left the voice channel."
         } else {
-            Err(CommandError::Execution(errors))
+            "Nothing has been played for a while, so I left the voice channel."
+        };
+
+        let Some(songbird) = self.songbird.clone() else {
+            self.alone_since = None;
+            self.idle_since = None;
+            return;
+        };
+        println!("guild {}: leaving automatically", self.id);
+        let out = self.default_reply_channel.clone();
+        if let Err(err) = self.leave(&out, &songbird).await {
+            eprintln!("failed to leave automatically: {err}");
+        }
+        self.print(format!(
+            "{reason} Use the `{CMD_JOIN}` command to invite me again."
+        ))
+        .await;
+    }
+}
+
+/// Explains a failed command to the user.
+fn describe_error(err: CommandError) -> String {
+    match err {
+        CommandError::Usage { message, topic } => format!("{}\n{}", message, topic.message()),
+        CommandError::Discord(message) => {
+            let message = format!("Internal error: {message}");
+            println!("{message}");
+            message
+        }
+        CommandError::UserVoiceChannelRequired => {
+            "You need to be in a voice channel to use this command".to_owned()
+        }
+        CommandError::BotVoiceChannelRequired => {
+            "I need to be in a voice chat to play a track. Use the `join` command to invite me."
+                .to_owned()
         }
+        CommandError::Execution(message) => message,
+        CommandError::NotInCommandChannel => {
+            "This channel isn't available for bot commands.".to_owned()
+        }
+    }
+}
+
+/// Looks up a voice channel of the guild by its mention (`<#id>`), id or name.
+fn find_voice_channel(guild: &Guild, channel: &str) -> Result<ChannelId, String> {
+    let voice_channels = || {
+        guild.channels.values().filter_map(|channel| match channel {
+            Channel::Guild(channel)
+                if matches!(channel.kind, ChannelType::Voice | ChannelType::Stage) =>
+            {
+                Some(channel)
+            }
+            _ => None,
+        })
+    };
+
+    let mention = channel
+        .strip_prefix("<#")
+        .and_then(|channel| channel.strip_suffix('>'));
+    if let Ok(id) = mention.unwrap_or(channel).parse::<u64>() {
+        if let Some(voice_channel) = voice_channels().find(|voice_channel| voice_channel.id.0 == id)
+        {
+            return Ok(voice_channel.id);
+        }
+        // a plain number might as well be the name of a channel
+        if mention.is_some() {
+            return Err(format!("<#{id}> isn't a voice channel of this server."));
+        }
+    }
+
+    let name = channel.trim_start_matches('#');
+    let mut matches: Vec<_> = voice_channels()
+        .filter(|voice_channel| voice_channel.name.eq_ignore_ascii_case(name))
+        .map(|voice_channel| voice_channel.id)
+        .collect();
+    match matches.len() {
+        0 => Err(format!("There's no voice channel named `{name}`.")),
+        1 => Ok(matches.remove(0)),
+        _ => Err(format!(
+            "There are several voice channels named `{name}`. Please use a mention like <#{}> instead.",
+            matches[0]
+        )),
+    }
+}
+
+/// Number of users other than bots within the bot's voice channel; `None` if the bot isn't in a
+/// voice channel.
+fn count_listeners(ctx: &Context, guild_id: GuildId) -> Option<usize> {
+    let guild = ctx.cache.guild(guild_id)?;
+    let bot_id = ctx.cache.current_user_id();
+    let channel_id = guild.voice_states.get(&bot_id)?.channel_id?;
+    Some(
+        guild
+            .voice_states
+            .values()
+            .filter(|voice_state| {
+                voice_state.channel_id == Some(channel_id) && voice_state.user_id != bot_id
+            })
+            .filter(|voice_state| {
+                let is_bot = match &voice_state.member {
+                    Some(member) => member.user.bot,
+                    None => ctx
+                        .cache
+                        .user(voice_state.user_id)
+                        .is_some_and(|user| user.bot),
+                };
+                !is_bot
+            })
+            .count(),
+    )
+}
+
+/// Waits until the given deadline; forever if there is none.
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Lists the tracks of a shared playlist along with its total length.
+fn describe_shared_playlist(playlist: &Playlist) -> String {
+    let (total, unsure) = playlist.total_duration();
+    let secs = total.as_secs();
+    let mut lines = vec![format!(
+        "**{}**{} · {} track(s) · {}:{:0>2}:{:0>2}{}",
+        playlist.name,
+        playlist
+            .owner
+            .map(|user_id| format!(" by <@{user_id}>"))
+            .unwrap_or_default(),
+        playlist.entries.len(),
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        if unsure {
+            " (or longer because some tracks have an unknown length)"
+        } else {
+            ""
+        }
+    )];
+    for (index, entry) in playlist
+        .entries
+        .iter()
+        .enumerate()
+        .take(MAX_SHOWN_PLAYLIST_TRACKS)
+    {
+        let duration = entry.duration.map_or_else(String::new, |duration| {
+            let secs = duration.as_secs();
+            format!(" ({}:{:0>2})", secs / 60, secs % 60)
+        });
+        lines.push(format!(
+            "{}. {}{} · <{}>",
+            index + 1,
+            entry.caption.as_deref().unwrap_or("(unknown track)"),
+            duration,
+            entry.url
+        ));
+    }
+    if playlist.entries.len() > MAX_SHOWN_PLAYLIST_TRACKS {
+        lines.push(format!(
+            "… and {} more",
+            playlist.entries.len() - MAX_SHOWN_PLAYLIST_TRACKS
+        ));
     }
+    lines.join("\n")
 }
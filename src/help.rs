@@ -7,6 +7,10 @@ pub const HELP_HELP: &str = CMD_HELP;
 pub const HELP_JOIN: &str = CMD_JOIN;
 pub const HELP_LEAVE: &str = CMD_LEAVE;
 pub const HELP_ENQUEUE: &str = CMD_ENQUEUE;
+pub const HELP_ENQUEUE_NEXT: &str = CMD_ENQUEUE_NEXT;
+pub const HELP_ENQUEUE_AT: &str = CMD_ENQUEUE_AT;
+pub const HELP_PLAYLIST: &str = CMD_PLAYLIST;
+pub const HELP_SHARED_PLAYLIST: &str = CMD_SHARED_PLAYLIST;
 pub const HELP_PAUSE: &str = CMD_PAUSE;
 pub const HELP_RESUME: &str = CMD_RESUME;
 pub const HELP_STOP: &str = CMD_STOP;
@@ -17,11 +21,18 @@ pub const HELP_NEXT: &str = CMD_NEXT;
 pub const HELP_PREV: &str = CMD_PREV;
 pub const HELP_REMOVE: &str = CMD_REMOVE;
 pub const HELP_SEEK: &str = CMD_SEEK;
+pub const HELP_LOOP: &str = CMD_LOOP;
 pub const HELP_NOW: &str = CMD_NOW;
+pub const HELP_INFO: &str = CMD_INFO;
 pub const HELP_REVERSE: &str = CMD_REVERSE;
 pub const HELP_QUOTA: &str = CMD_QUOTA;
+pub const HELP_UNDO: &str = CMD_UNDO;
+pub const HELP_REDO: &str = CMD_REDO;
 pub const HELP_MOVE: &str = CMD_MOVE;
 pub const HELP_WHEN: &str = CMD_WHEN;
+pub const HELP_HISTORY: &str = CMD_HISTORY;
+pub const HELP_STATS: &str = CMD_STATS;
+pub const HELP_STATUS: &str = CMD_STATUS;
 
 pub const HELP_TRACK_INDEX: &str = "track-index";
 pub const HELP_TRACK_RANGE: &str = "track-range";
@@ -34,6 +45,10 @@ pub enum HelpTopic {
     Join,
     Leave,
     Enqueue,
+    EnqueueNext,
+    EnqueueAt,
+    Playlist,
+    SharedPlaylist,
     Pause,
     Resume,
     Stop,
@@ -44,11 +59,18 @@ pub enum HelpTopic {
     Prev,
     Remove,
     Seek,
+    Loop,
     Now,
+    Info,
     Reverse,
     Quota,
+    Undo,
+    Redo,
     Move,
     When,
+    History,
+    Stats,
+    Status,
     TrackIndex,
     TrackRange,
     TrackSet,
@@ -77,18 +99,29 @@ impl HelpTopic {
                 writeln!(help, "{}", HelpTopic::Pause.overview()).unwrap();
                 writeln!(help, "{}", HelpTopic::Resume.overview()).unwrap();
                 writeln!(help, "{}", HelpTopic::Seek.overview()).unwrap();
+                writeln!(help, "{}", HelpTopic::Loop.overview()).unwrap();
 
                 writeln!(help, "**Queue management**").unwrap();
                 writeln!(help, "{}", HelpTopic::Enqueue.overview()).unwrap();
+                writeln!(help, "{}", HelpTopic::EnqueueNext.overview()).unwrap();
+                writeln!(help, "{}", HelpTopic::EnqueueAt.overview()).unwrap();
+                writeln!(help, "{}", HelpTopic::Playlist.overview()).unwrap();
+                writeln!(help, "{}", HelpTopic::SharedPlaylist.overview()).unwrap();
                 writeln!(help, "{}", HelpTopic::Remove.overview()).unwrap();
                 writeln!(help, "{}", HelpTopic::Reverse.overview()).unwrap();
                 writeln!(help, "{}", HelpTopic::Move.overview()).unwrap();
                 writeln!(help, "{}", HelpTopic::Quota.overview()).unwrap();
+                writeln!(help, "{}", HelpTopic::Undo.overview()).unwrap();
+                writeln!(help, "{}", HelpTopic::Redo.overview()).unwrap();
 
                 writeln!(help, "**Status info**").unwrap();
                 writeln!(help, "{}", HelpTopic::Print.overview()).unwrap();
                 writeln!(help, "{}", HelpTopic::Now.overview()).unwrap();
+                writeln!(help, "{}", HelpTopic::Info.overview()).unwrap();
                 writeln!(help, "{}", HelpTopic::When.overview()).unwrap();
+                writeln!(help, "{}", HelpTopic::History.overview()).unwrap();
+                writeln!(help, "{}", HelpTopic::Stats.overview()).unwrap();
+                writeln!(help, "{}", HelpTopic::Status.overview()).unwrap();
 
                 writeln!(help, "**Bot control**").unwrap();
                 writeln!(help, "{}", HelpTopic::Join.overview()).unwrap();
@@ -135,6 +168,10 @@ impl HelpTopic {
                     Self::Join,
                     Self::Leave,
                     Self::Enqueue,
+                    Self::EnqueueNext,
+                    Self::EnqueueAt,
+                    Self::Playlist,
+                    Self::SharedPlaylist,
                     Self::Pause,
                     Self::Resume,
                     Self::Stop,
@@ -145,11 +182,18 @@ impl HelpTopic {
                     Self::Prev,
                     Self::Remove,
                     Self::Seek,
+                    Self::Loop,
                     Self::Now,
+                    Self::Info,
                     Self::Reverse,
                     Self::Quota,
+                    Self::Undo,
+                    Self::Redo,
                     Self::Move,
                     Self::When,
+                    Self::History,
+                    Self::Stats,
+                    Self::Status,
                     Self::TrackIndex,
                     Self::TrackRange,
                     Self::TrackSet,
@@ -170,11 +214,17 @@ impl HelpTopic {
                 writeln!(help, "Most functions of the bot will only be available when the bot is in a voice chat. \
                     To make it join a voice channel you have to join the channel first and then issue this command. \
                     The bot will follow you and display a welcome message in the default text channel to indicate it is ready for playback.").unwrap();
+                writeln!(help, "Alternatively DJs can send the bot to a voice channel by mentioning it or by its name - there's no need to be in that channel yourself.").unwrap();
                 writeln!(
                     help,
-                    "If the bot is already in a voice channel it will move over."
+                    "If the bot is already in a voice channel it will move over and continue the playback there. Only DJs can move it while others are still listening."
                 )
                 .unwrap();
+                writeln!(help, "In a stage channel the bot becomes a speaker (or raises its hand if it isn't allowed to) and shows the current track as the stage's topic. \
+                    If the bot had to start the stage for this, it will end it again when leaving.").unwrap();
+                writeln!(help, "Some common use cases:").unwrap();
+                writeln!(help, "· `{CMD_JOIN}` - the bot joins your voice channel").unwrap();
+                writeln!(help, "· `{CMD_JOIN} #listening-room` or `{CMD_JOIN} listening-room` - the bot joins the voice channel named _listening-room_").unwrap();
                 writeln!(
                     help,
                     "Use the `{CMD_LEAVE}` command to make it leave again."
@@ -187,6 +237,7 @@ impl HelpTopic {
                 writeln!(help, "{}", self.overview()).unwrap();
                 writeln!(help, "The playback will stop, but the bot will remember the current playlist and playback position.").unwrap();
                 writeln!(help, "Use the `{CMD_JOIN}` command to make it join again.").unwrap();
+                writeln!(help, "The bot also leaves on its own when nobody has been listening or nothing has been played for a while.").unwrap();
                 help
             }
             HelpTopic::Enqueue => {
@@ -211,9 +262,57 @@ impl HelpTopic {
                 writeln!(help, "Pro-tip: surround your URLs with backticks (\"`\") to prevent Discord from flooding the channel with auto-previews. Triple-backticks (\\`\\`\\``) are also supported which comes in handy for multi-line enqueues.").unwrap();
                 help
             }
+            HelpTopic::EnqueueNext => {
+                let mut help = String::new();
+                writeln!(help, "{}", self.overview()).unwrap();
+                writeln!(help, "Works like `{CMD_ENQUEUE}` but places the tracks right after the current one.").unwrap();
+                writeln!(help, "Only DJs can place tracks ahead of the tracks of other users.").unwrap();
+                writeln!(help, "If the quota is enabled, only tracks within your quota jump the queue; the others are held back as if you had used `{CMD_ENQUEUE}`. DJs aren't limited by the quota.").unwrap();
+                writeln!(help, "see `{CMD_HELP} {HELP_QUOTA}` for more details on the quota").unwrap();
+                help
+            }
+            HelpTopic::EnqueueAt => {
+                let mut help = String::new();
+                writeln!(help, "{}", self.overview()).unwrap();
+                writeln!(help, "Works like `{CMD_ENQUEUE}` but places the tracks at the given position.").unwrap();
+                writeln!(help, "Tracks can only be placed after the current one and ahead of the tracks which are held back by the quota. Only DJs can place tracks ahead of the tracks of other users.").unwrap();
+                writeln!(help, "If the quota is enabled, only tracks within your quota are placed at the given position; the others are held back as if you had used `{CMD_ENQUEUE}`. DJs aren't limited by the quota.").unwrap();
+                writeln!(help, "Some common use cases:").unwrap();
+                writeln!(help, "· `{CMD_ENQUEUE_AT} 5 <track-reference>` - inserts the track as the new track #5").unwrap();
+                writeln!(help, "· `{CMD_ENQUEUE_AT} +2 <track-reference>` - inserts the track to be played after the next one").unwrap();
+                writeln!(help, "see `{CMD_HELP} {HELP_TRACK_INDEX}` for more options on placing tracks").unwrap();
+                help
+            }
+            HelpTopic::Playlist => {
+                let mut help = String::new();
+                writeln!(help, "{}", self.overview()).unwrap();
+                writeln!(help, "Your personal playlists are available on every server the bot is on.").unwrap();
+                writeln!(help, "· `{CMD_PLAYLIST} save <name> [<track-set>]` - saves the selected tracks (the whole queue by default) as a playlist; an existing playlist of the same name will be replaced").unwrap();
+                writeln!(help, "· `{CMD_PLAYLIST} load <name>` - enqueues the tracks of a playlist").unwrap();
+                writeln!(help, "· `{CMD_PLAYLIST} list` - lists your playlists").unwrap();
+                writeln!(help, "· `{CMD_PLAYLIST} delete <name>` - deletes a playlist").unwrap();
+                writeln!(help, "Playlists only remember where the tracks can be found (and their comments), so loading a playlist takes as long as enqueueing its tracks one by one.").unwrap();
+                writeln!(help, "see `{CMD_HELP} {HELP_TRACK_SET}` for more options on selecting tracks").unwrap();
+                help
+            }
+            HelpTopic::SharedPlaylist => {
+                let mut help = String::new();
+                writeln!(help, "{}", self.overview()).unwrap();
+                writeln!(help, "Shared playlists belong to this server. Everybody can play them, but only their creator and DJs can change them.").unwrap();
+                writeln!(help, "· `{CMD_SHARED_PLAYLIST} create <name> [<track-set>]` - creates a playlist; optionally with the selected tracks of the queue").unwrap();
+                writeln!(help, "· `{CMD_SHARED_PLAYLIST} add <name> <track-set>` - appends the selected tracks of the queue").unwrap();
+                writeln!(help, "· `{CMD_SHARED_PLAYLIST} move <name> <number> <position>` - moves a track of the playlist to another position").unwrap();
+                writeln!(help, "· `{CMD_SHARED_PLAYLIST} show <name>` - shows the tracks and the total length of a playlist").unwrap();
+                writeln!(help, "· `{CMD_SHARED_PLAYLIST} play <name>` - enqueues the tracks of a playlist").unwrap();
+                writeln!(help, "· `{CMD_SHARED_PLAYLIST} list` - lists the server's playlists").unwrap();
+                writeln!(help, "· `{CMD_SHARED_PLAYLIST} delete <name>` - deletes a playlist").unwrap();
+                writeln!(help, "see `{CMD_HELP} {HELP_TRACK_SET}` for more options on selecting tracks").unwrap();
+                help
+            }
             HelpTopic::Pause => {
                 let mut help = String::new();
                 writeln!(help, "{}", self.overview()).unwrap();
+                writeln!(help, "A paused track stays paused until somebody uses the `{CMD_RESUME}` command - even if everybody left and came back in the meantime.").unwrap();
                 help
             }
             HelpTopic::Resume => {
@@ -236,8 +335,9 @@ impl HelpTopic {
             HelpTopic::Print => {
                 let mut help = String::new();
                 writeln!(help, "{}", self.overview()).unwrap();
-                writeln!(help, "By default this command displays 2 tracks before the current and 15 tracks after it (`-2..+15`).\
-                    If the output becomes too long, the bot will split it into multiple messages.").unwrap();
+                writeln!(help, "By default this command displays 2 tracks before the current and 12 tracks after it (`-2..+12`).\
+                    Up to 15 tracks are shown at once; use the ◀ and ▶ buttons below the queue to page through the rest of it.").unwrap();
+                writeln!(help, "The current track is marked with ▶; tracks marked with ⏳ are held back because the user who added them reached the `{CMD_QUOTA}`.").unwrap();
                 writeln!(help, "Some common use cases:").unwrap();
                 writeln!(
                     help,
@@ -334,9 +434,31 @@ impl HelpTopic {
                 .unwrap();
                 help
             }
+            HelpTopic::Loop => {
+                let mut help = String::new();
+                writeln!(help, "{}", self.overview()).unwrap();
+                writeln!(help, "Switches repeating the current track on or off. While it's on, the current track starts over instead of advancing to the next one; `{CMD_NEXT}` and `{CMD_GOTO}` still work as usual.").unwrap();
+                writeln!(help, "Reacting with 🔁 to the now-playing panel does the same.").unwrap();
+                help
+            }
             HelpTopic::Now => {
                 let mut help = String::new();
                 writeln!(help, "{}", self.overview()).unwrap();
+                writeln!(help, "The bot shows the current track in a single panel which is kept up to date while playing. This command moves the panel to the bottom of the channel.").unwrap();
+                writeln!(help, "The panel's buttons pause, resume, skip, go back, stop and loop the playback; they can be used by everybody in a voice channel.").unwrap();
+                writeln!(help, "Reacting to the panel works as well: ⏭ skips, ⏸ pauses, ▶ resumes, ⏹ stops and 🔁 loops the current track.").unwrap();
+                writeln!(help, "Audiotool tracks also show their artist's profile and how often they have been played, favoured and commented. Use `{CMD_INFO}` to read the latest comments.").unwrap();
+                help
+            }
+            HelpTopic::Info => {
+                let mut help = String::new();
+                writeln!(help, "{}", self.overview()).unwrap();
+                writeln!(help, "The track page, the artist's profile, the track it's a remix of and how often it has been played, favoured and commented are shown if the track's source provides them.").unwrap();
+                writeln!(help, "The latest comments on the track page are shown as well.").unwrap();
+                writeln!(help, "Some common use cases:").unwrap();
+                writeln!(help, "· `{CMD_INFO}` - displays details about the current track").unwrap();
+                writeln!(help, "· `{CMD_INFO} +1` or `{CMD_INFO} next` - displays details about the next track").unwrap();
+                writeln!(help, "see `{CMD_HELP} {HELP_TRACK_INDEX}` for more options").unwrap();
                 help
             }
             HelpTopic::Reverse => {
@@ -380,6 +502,22 @@ impl HelpTopic {
                 .unwrap();
                 help
             }
+            HelpTopic::Undo => {
+                let mut help = String::new();
+                writeln!(help, "{}", self.overview()).unwrap();
+                writeln!(help, "Reverts the latest change of the queue; e.g. after removing the wrong tracks by accident.").unwrap();
+                writeln!(help, "Enqueueing, inserting, removing, moving and reversing tracks as well as jumping to other tracks and changing the quota can be undone.").unwrap();
+                writeln!(help, "Use `{CMD_UNDO}` repeatedly to go back further; only the last 50 changes are remembered.").unwrap();
+                writeln!(help, "Undoing a change doesn't interrupt the current track unless the change was about jumping to another track.").unwrap();
+                help
+            }
+            HelpTopic::Redo => {
+                let mut help = String::new();
+                writeln!(help, "{}", self.overview()).unwrap();
+                writeln!(help, "Makes the latest change again which has been reverted by `{CMD_UNDO}`.").unwrap();
+                writeln!(help, "Undone changes can't be redone anymore once the queue has been changed otherwise.").unwrap();
+                help
+            }
             HelpTopic::Move => {
                 let mut help = String::new();
                 writeln!(help, "{}", self.overview()).unwrap();
@@ -435,6 +573,39 @@ impl HelpTopic {
                 .unwrap();
                 help
             }
+            HelpTopic::History => {
+                let mut help = String::new();
+                writeln!(help, "{}", self.overview()).unwrap();
+                writeln!(help, "Lists the most recently played tracks; the latest one comes first.").unwrap();
+                writeln!(help, "Each entry shows when the track started, how many users were listening, and whether it has been played completely or who skipped it.").unwrap();
+                writeln!(help, "Without a parameter the last 10 tracks will be shown; at most 25 at once.").unwrap();
+                writeln!(help, "· `{CMD_HISTORY} 20` - shows the last 20 played tracks").unwrap();
+                help
+            }
+            HelpTopic::Stats => {
+                let mut help = String::new();
+                writeln!(help, "{}", self.overview()).unwrap();
+                writeln!(help, "Summarizes what has been played on this server; e.g. for a weekly recap.").unwrap();
+                writeln!(help, "Without a category all of them will be shown at once: the total listening time, top tracks, top artists, top genres and the most active enqueuers.").unwrap();
+                writeln!(help, "Categories: `tracks`, `artists`, `genres`, `enqueuers` and `time`").unwrap();
+                writeln!(help, "Periods: `today`, `week` (default), `month`, `year`, `all`, a number of days like `14d`, a single date like `2024-05-01` or a range of dates like `2024-05-01..2024-05-31`").unwrap();
+                writeln!(help, "Some common use cases:").unwrap();
+                writeln!(help, "· `{CMD_STATS}` - summarizes the last 7 days").unwrap();
+                writeln!(help, "· `{CMD_STATS} artists month` - lists the most played artists of the last 30 days").unwrap();
+                writeln!(help, "· `{CMD_STATS} time 2024-05-01..2024-05-31` - shows how much music has been played in May 2024").unwrap();
+                help
+            }
+            HelpTopic::Status => {
+                let mut help = String::new();
+                writeln!(help, "{}", self.overview()).unwrap();
+                writeln!(help, "Requests to Audiotool time out after a while and are retried a few times when the servers seem to be struggling. \
+                        After too many failures in a row the bot will stop sending requests for a minute to give the servers some rest. \
+                        Enqueueing Audiotool tracks will fail during that time.").unwrap();
+                writeln!(help, "This command tells whether the connection is healthy and what went wrong most recently.").unwrap();
+                writeln!(help, "If the bot has been configured with an Audiotool account, it also tells whether the bot is logged in. Private tracks can only be played while it is.").unwrap();
+                writeln!(help, "It also tells what the bot is busy with on this server. Commands are processed one after another; a command which takes longer than 30 seconds will be cancelled.").unwrap();
+                help
+            }
             HelpTopic::TrackIndex => {
                 let mut help = String::new();
                 writeln!(help, "{}", self.overview()).unwrap();
@@ -528,9 +699,13 @@ impl HelpTopic {
     fn overview(self) -> String {
         match self {
             HelpTopic::Help => format!("`{PREFIX}{CMD_HELP} [<command>]` - shows a help page for the given command or topic"),
-            HelpTopic::Join => format!("`{PREFIX}{CMD_JOIN}` - makes the bot follow you into a voice channel"),
+            HelpTopic::Join => format!("`{PREFIX}{CMD_JOIN} [<channel>]` - makes the bot follow you or join the given voice channel"),
             HelpTopic::Leave => format!("`{PREFIX}{CMD_LEAVE}` - makes the bot leave the voice channel"),
             HelpTopic::Enqueue => format!("`{PREFIX}{CMD_ENQUEUE} <track-reference>` - adds tracks or entire playlists to the playback queue"),
+            HelpTopic::EnqueueNext => format!("`{PREFIX}{CMD_ENQUEUE_NEXT} <track-reference>` - adds tracks to be played right after the current one"),
+            HelpTopic::EnqueueAt => format!("`{PREFIX}{CMD_ENQUEUE_AT} <track-index> <track-reference>` - adds tracks at the given position of the queue"),
+            HelpTopic::Playlist => format!("`{PREFIX}{CMD_PLAYLIST} save|load|list|delete [<name>]` - manages your personal playlists"),
+            HelpTopic::SharedPlaylist => format!("`{PREFIX}{CMD_SHARED_PLAYLIST} create|add|move|show|play|list|delete [<name>]` - manages the server's shared playlists"),
             HelpTopic::Pause => format!("`{PREFIX}{CMD_PAUSE}` - pauses the playback of the current track; use `{CMD_RESUME}` to resume the playback"),
             HelpTopic::Resume => format!("`{PREFIX}{CMD_RESUME}` - resumes the playback of a paused track"),
             HelpTopic::Stop => format!("`{PREFIX}{CMD_STOP}` - stops the playback; use `{CMD_PLAY}` to restart the stopped track"),
@@ -541,11 +716,18 @@ impl HelpTopic {
             HelpTopic::Prev => format!("`{PREFIX}{CMD_PREV}` - go back to the previous track"),
             HelpTopic::Remove => format!("`{PREFIX}{CMD_REMOVE} <track-set>` - removes one or more tracks from the playback queue"),
             HelpTopic::Seek => format!("`{PREFIX}{CMD_SEEK} <position>` - seeks into the current track"),
-            HelpTopic::Now => format!("`{PREFIX}{CMD_NOW}` - displays the current track with its playback position"),
+            HelpTopic::Loop => format!("`{PREFIX}{CMD_LOOP}` - repeats the current track until switched off again"),
+            HelpTopic::Now => format!("`{PREFIX}{CMD_NOW}` - displays the now-playing panel with the current track and playback buttons"),
+            HelpTopic::Info => format!("`{PREFIX}{CMD_INFO} [<track-index>]` - displays details and the latest comments of a track"),
             HelpTopic::Reverse => format!("`{PREFIX}{CMD_REVERSE} <track-set>` - reverses or swaps two or more tracks"),
             HelpTopic::Quota => format!("`{PREFIX}{CMD_QUOTA} [<quota>]` - limits the number of tracks a single user can enqueue"),
+            HelpTopic::Undo => format!("`{PREFIX}{CMD_UNDO}` - reverts the latest change of the queue"),
+            HelpTopic::Redo => format!("`{PREFIX}{CMD_REDO}` - makes a change again which has been undone"),
             HelpTopic::Move => format!("`{PREFIX}{CMD_MOVE} <track-set> to <track_index>` - moves one or multiple tracks to a new location"),
             HelpTopic::When => format!("`{PREFIX}{CMD_WHEN} <track-index>` - tells how long to wait until the given track will be played"),
+            HelpTopic::History => format!("`{PREFIX}{CMD_HISTORY} [<count>]` - lists the recently played tracks"),
+            HelpTopic::Stats => format!("`{PREFIX}{CMD_STATS} [<category>] [<period>]` - shows the top tracks, artists, genres and enqueuers"),
+            HelpTopic::Status => format!("`{PREFIX}{CMD_STATUS}` - displays the health of the connection to Audiotool and the bot's workload"),
             HelpTopic::TrackIndex => "`<n>`|`+<n>`|`-<n>`|`start`|`now`|`end`|`next`|`prev` - a track-index allows to specify a single track within the queue".to_string(),
             HelpTopic::TrackRange => "`[<from>]..[<to>]`|`all`|`history`|`future`|`now`|`other` - a track-range can be used to specify one or more consecutive tracks".to_string(),
             HelpTopic::TrackSet => "`<range1>,<range2>,…`|`other` - a track-set is an arbitrary selection of tracks".to_string(),
@@ -564,6 +746,10 @@ impl FromStr for HelpTopic {
             HELP_JOIN => Ok(Self::Join),
             HELP_LEAVE => Ok(Self::Leave),
             HELP_ENQUEUE => Ok(Self::Enqueue),
+            HELP_ENQUEUE_NEXT => Ok(Self::EnqueueNext),
+            HELP_ENQUEUE_AT => Ok(Self::EnqueueAt),
+            HELP_PLAYLIST => Ok(Self::Playlist),
+            HELP_SHARED_PLAYLIST => Ok(Self::SharedPlaylist),
             HELP_PAUSE => Ok(Self::Pause),
             HELP_RESUME => Ok(Self::Resume),
             HELP_STOP => Ok(Self::Stop),
@@ -574,11 +760,18 @@ impl FromStr for HelpTopic {
             HELP_PREV => Ok(Self::Prev),
             HELP_REMOVE => Ok(Self::Remove),
             HELP_SEEK => Ok(Self::Seek),
+            HELP_LOOP => Ok(Self::Loop),
             HELP_NOW => Ok(Self::Now),
+            HELP_INFO => Ok(Self::Info),
             HELP_REVERSE => Ok(Self::Reverse),
             HELP_QUOTA => Ok(Self::Quota),
+            HELP_UNDO => Ok(Self::Undo),
+            HELP_REDO => Ok(Self::Redo),
             HELP_MOVE => Ok(Self::Move),
             HELP_WHEN => Ok(Self::When),
+            HELP_HISTORY => Ok(Self::History),
+            HELP_STATS => Ok(Self::Stats),
+            HELP_STATUS => Ok(Self::Status),
             HELP_TRACK_INDEX => Ok(Self::TrackIndex),
             HELP_TRACK_RANGE => Ok(Self::TrackRange),
             HELP_TRACK_SET => Ok(Self::TrackSet),
@@ -595,6 +788,10 @@ impl fmt::Display for HelpTopic {
             HelpTopic::Join => HELP_JOIN,
             HelpTopic::Leave => HELP_LEAVE,
             HelpTopic::Enqueue => HELP_ENQUEUE,
+            HelpTopic::EnqueueNext => HELP_ENQUEUE_NEXT,
+            HelpTopic::EnqueueAt => HELP_ENQUEUE_AT,
+            HelpTopic::Playlist => HELP_PLAYLIST,
+            HelpTopic::SharedPlaylist => HELP_SHARED_PLAYLIST,
             HelpTopic::Pause => HELP_PAUSE,
             HelpTopic::Resume => HELP_RESUME,
             HelpTopic::Stop => HELP_STOP,
@@ -605,11 +802,18 @@ impl fmt::Display for HelpTopic {
             HelpTopic::Prev => HELP_PREV,
             HelpTopic::Remove => HELP_REMOVE,
             HelpTopic::Seek => HELP_SEEK,
+            HelpTopic::Loop => HELP_LOOP,
             HelpTopic::Now => HELP_NOW,
+            HelpTopic::Info => HELP_INFO,
             HelpTopic::Reverse => HELP_REVERSE,
             HelpTopic::Quota => HELP_QUOTA,
+            HelpTopic::Undo => HELP_UNDO,
+            HelpTopic::Redo => HELP_REDO,
             HelpTopic::Move => HELP_MOVE,
             HelpTopic::When => HELP_WHEN,
+            HelpTopic::History => HELP_HISTORY,
+            HelpTopic::Stats => HELP_STATS,
+            HelpTopic::Status => HELP_STATUS,
             HelpTopic::TrackIndex => HELP_TRACK_INDEX,
             HelpTopic::TrackRange => HELP_TRACK_RANGE,
             HelpTopic::TrackSet => HELP_TRACK_SET,
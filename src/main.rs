@@ -16,6 +16,7 @@ mod guild;
 mod message;
 
 use audiotool::AudiotoolHttpClient;
+use serenity::model::application::interaction::Interaction;
 use serenity::model::channel::Channel;
 use serenity::model::event::MessageUpdateEvent;
 use serenity::model::guild::{Guild, Member, PartialGuild, Role};
@@ -30,7 +31,7 @@ use serenity::client::Context;
 const PREFIX: &str = "/al ";
 
 lazy_static! {
-    pub static ref AUDIOTOOL_HTTP_CLIENT: AudiotoolHttpClient = AudiotoolHttpClient::default();
+    pub static ref AUDIOTOOL_HTTP_CLIENT: AudiotoolHttpClient = AudiotoolHttpClient::from_env();
 }
 
 use serenity::{
@@ -40,7 +41,16 @@ use serenity::{
 };
 
 mod help;
+mod panel;
+mod play_history;
+mod playlist;
+mod presence;
 mod queue;
+mod queue_history;
+mod queue_page;
+mod stage;
+mod stats;
+mod storage;
 mod track;
 
 static STOPPED: AtomicBool = AtomicBool::new(false);
@@ -57,30 +67,18 @@ async fn main() {
         // Configure Guild and channel roles (audiotool)
         const GUILD_ID: GuildId = GuildId(317447437650231296);
         let guild = GUILD_STATES.get_guild_state(GUILD_ID).await;
-        guild
-            .add_command_channel(ChannelId(344959567538421761))
-            .await;
-        guild
-            .add_command_channel(ChannelId(683877804420628480))
-            .await;
-        guild
-            .add_command_channel(ChannelId(429076647120207882))
-            .await;
-        guild
-            .set_default_output_channel(ChannelId(344959567538421761))
-            .await;
+        guild.add_command_channel(ChannelId(344959567538421761));
+        guild.add_command_channel(ChannelId(683877804420628480));
+        guild.add_command_channel(ChannelId(429076647120207882));
+        guild.set_default_output_channel(ChannelId(344959567538421761));
     }
 
     {
         // Configure Guild and channel roles (own server)
         const GUILD_ID: GuildId = GuildId(880206012508938330);
         let guild = GUILD_STATES.get_guild_state(GUILD_ID).await;
-        guild
-            .add_command_channel(ChannelId(880206012508938333))
-            .await;
-        guild
-            .set_default_output_channel(ChannelId(880206012508938333))
-            .await;
+        guild.add_command_channel(ChannelId(880206012508938333));
+        guild.set_default_output_channel(ChannelId(880206012508938333));
     }
 
     if let Err(err) = validate_token(&token) {
@@ -94,7 +92,7 @@ async fn main() {
             println!("firing keep alive …");
             AUDIOTOOL_HTTP_CLIENT.keep_alive().await;
             // println!("firing keep alive done");
-            for _ in 0..60 {
+            for _ in 0..AUDIOTOOL_HTTP_CLIENT.keep_alive_delay().as_secs() {
                 if STOPPED.load(Ordering::Relaxed) {
                     return;
                 }
@@ -109,7 +107,8 @@ async fn main() {
         GatewayIntents::GUILD_VOICE_STATES
             | GatewayIntents::MESSAGE_CONTENT
             | GatewayIntents::GUILDS
-            | GatewayIntents::GUILD_MESSAGES,
+            | GatewayIntents::GUILD_MESSAGES
+            | GatewayIntents::GUILD_MESSAGE_REACTIONS,
     )
     .event_handler(GlobalHandler)
     .register_songbird()
@@ -205,8 +204,8 @@ impl EventHandler for GlobalHandler {
     ) {
         println!("event received: guild_create({}:{})", guild.name, guild.id);
         let guild = GUILD_STATES.get_guild_state(guild.id).await;
-        guild.set_http(ctx.http.clone()).await;
-        guild.print_default("Ready to party! 🎵🕺🎶").await;
+        guild.set_http(ctx.http.clone());
+        guild.print_default("Ready to party! 🎵🕺🎶");
     }
 
     async fn guild_delete(
@@ -370,8 +369,12 @@ impl EventHandler for GlobalHandler {
         println!("event received: message_update");
     }
 
-    async fn reaction_add(&self, _ctx: Context, _add_reaction: serenity::model::channel::Reaction) {
+    async fn reaction_add(&self, ctx: Context, add_reaction: serenity::model::channel::Reaction) {
         println!("event received: reaction_add");
+        if let Some(guild_id) = add_reaction.guild_id {
+            let guild = GUILD_STATES.get_guild_state(guild_id).await;
+            guild.handle_reaction(&ctx, &add_reaction).await;
+        }
     }
 
     async fn reaction_remove(
@@ -399,8 +402,9 @@ impl EventHandler for GlobalHandler {
         println!("event received: presence_update");
     }
 
-    async fn ready(&self, _ctx: Context, _data_about_bot: Ready) {
+    async fn ready(&self, ctx: Context, _data_about_bot: Ready) {
         println!("event received: ready");
+        presence::set_shard(ctx.shard);
     }
 
     async fn resume(&self, _ctx: Context, _: serenity::model::event::ResumedEvent) {
@@ -435,8 +439,12 @@ impl EventHandler for GlobalHandler {
         println!("event received: voice_server_update");
     }
 
-    async fn voice_state_update(&self, _ctx: Context, _old: Option<VoiceState>, _new: VoiceState) {
+    async fn voice_state_update(&self, ctx: Context, _old: Option<VoiceState>, new: VoiceState) {
         println!("event received: voice_state_update");
+        if let Some(guild_id) = new.guild_id {
+            let guild = GUILD_STATES.get_guild_state(guild_id).await;
+            guild.handle_voice_state_update(&ctx, &new);
+        }
     }
 
     async fn webhook_update(
@@ -511,4 +519,14 @@ impl EventHandler for GlobalHandler {
     ) {
         println!("event received: thread_members_update");
     }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        println!("event received: interaction_create");
+        if let Interaction::MessageComponent(component) = interaction {
+            if let Some(guild_id) = component.guild_id {
+                let guild = GUILD_STATES.get_guild_state(guild_id).await;
+                guild.handle_component(&ctx, &component).await;
+            }
+        }
+    }
 }
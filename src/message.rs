@@ -1,14 +1,50 @@
-use std::{fmt, sync::Arc};
+use lazy_static::lazy_static;
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex as StdMutex},
+    time::{Duration, Instant, SystemTime},
+};
 
 use serenity::{
-    http::Http,
-    model::{channel::Message, id::ChannelId},
+    builder::{CreateComponents, CreateEmbed, CreateMessage},
+    http::{routing::Route, Http},
+    model::{
+        channel::{AttachmentType, Message},
+        id::{ChannelId, MessageId},
+    },
 };
+use tokio::sync::{mpsc, oneshot};
+
+/// Discord's limit is 2000 characters; leaves some room for closing code blocks.
+const MAX_MESSAGE_LENGTH: usize = 1950;
+
+/// Longer texts are attached as a file instead of flooding the channel.
+const MAX_MESSAGE_PARTS: usize = 5;
+
+/// Texts printed within this window are combined into a single message.
+const COALESCE_WINDOW: Duration = Duration::from_millis(500);
+
+/// Status messages beyond this number within a single message are skipped.
+const MAX_STATUS_MESSAGES: usize = 3;
+
+const CODE_FENCE: &str = "```";
+
+const TOO_LONG_NOTE: &str = "This is a bit too long for a chat message, so here's a file instead.";
+const TOO_LONG_FILE_NAME: &str = "message.md";
+
+lazy_static! {
+    /// Outgoing messages of every channel the bot is writing to.
+    static ref OUTBOXES: StdMutex<HashMap<ChannelId, mpsc::UnboundedSender<Outgoing>>> =
+        StdMutex::default();
+}
 
 #[derive(Default, Clone)]
 pub struct MessageChannel {
     channel: Option<ChannelId>,
     http: Option<Arc<Http>>,
+    /// status messages are deleted again after this long
+    status_lifetime: Option<Duration>,
 }
 
 impl MessageChannel {
@@ -16,6 +52,7 @@ impl MessageChannel {
         Self {
             channel: Some(channel),
             http: Some(http),
+            status_lifetime: None,
         }
     }
 
@@ -27,41 +64,573 @@ impl MessageChannel {
         self.http = Some(http);
     }
 
+    pub fn set_status_lifetime(&mut self, lifetime: Option<Duration>) {
+        self.status_lifetime = lifetime;
+    }
+
+    pub fn channel_id(&self) -> Option<ChannelId> {
+        self.channel
+    }
+
+    pub fn http(&self) -> Option<&Arc<Http>> {
+        self.http.as_ref()
+    }
+
     pub async fn print(&self, message: impl fmt::Display) {
-        self.print_raw(message).await;
+        self.print_raw(message, false, None).await;
     }
 
-    async fn print_raw(&self, message: impl fmt::Display) {
-        if let Self {
-            channel: Some(channel),
-            http: Some(ref http),
-        } = *self
-        {
-            let message = message.to_string();
-            let mut lines: Vec<&str> = message.lines().collect();
-            let mut msg = lines.remove(0).to_string();
-            for line in lines {
-                if msg.len() + 1 + line.len() >= 1950 {
-                    // real limit is 2000
-                    Self::check_msg(channel.say(&http, msg.clone()).await);
-                    msg.clear();
+    /// Prints a confirmation which isn't worth flooding the channel with; might be summarized or
+    /// skipped if there are too many of them at once, and will be deleted again after the status
+    /// lifetime (if any).
+    pub async fn print_status(&self, message: impl fmt::Display) {
+        self.print_raw(message, true, None).await;
+    }
+
+    /// Prints the message as a reply to the given message (e.g. the command it's answering).
+    pub async fn reply(&self, to: MessageId, message: impl fmt::Display) {
+        self.print_raw(message, false, Some(to)).await;
+    }
+
+    async fn print_raw(
+        &self,
+        message: impl fmt::Display,
+        is_status: bool,
+        reply_to: Option<MessageId>,
+    ) {
+        let text = message.to_string();
+        if split_message(&text, MAX_MESSAGE_LENGTH).len() > MAX_MESSAGE_PARTS {
+            self.attach(TOO_LONG_NOTE, TOO_LONG_FILE_NAME, text.into_bytes())
+                .await;
+            return;
+        }
+        self.post(Outgoing::Text {
+            text: OutgoingText {
+                lifetime: self.status_lifetime.filter(|_| is_status),
+                text,
+                is_status,
+            },
+            reply_to,
+        });
+    }
+
+    /// Sends an embed; returns the message unless sending failed.
+    pub async fn send_embed(
+        &self,
+        embed: CreateEmbed,
+        components: CreateComponents,
+    ) -> Option<Message> {
+        let (sent, message) = oneshot::channel();
+        self.post(Outgoing::Embed {
+            embed,
+            components,
+            sent,
+        });
+        message.await.ok().flatten()
+    }
+
+    /// Sends the given data as a file along with the message.
+    #[allow(clippy::unused_async)] // delivery is up to the outbox
+    pub async fn attach(&self, message: impl fmt::Display, file_name: &str, data: Vec<u8>) {
+        self.post(Outgoing::File {
+            text: message.to_string(),
+            file_name: file_name.to_owned(),
+            data,
+        });
+    }
+
+    /// Hands the message over to the channel's outbox, which keeps messages in order.
+    fn post(&self, message: Outgoing) {
+        let (Some(channel), Some(http)) = (self.channel, &self.http) else {
+            return;
+        };
+        let mut outboxes = OUTBOXES.lock().expect("failed to lock outboxes");
+        let outbox = outboxes
+            .entry(channel)
+            .or_insert_with(|| Outbox::spawn(channel, Arc::clone(http)));
+        if let Err(mpsc::error::SendError(message)) = outbox.send(message) {
+            // the outbox died; start over
+            let outbox = Outbox::spawn(channel, Arc::clone(http));
+            let _ = outbox.send(message);
+            outboxes.insert(channel, outbox);
+        }
+    }
+}
+
+struct OutgoingText {
+    text: String,
+    /// whether the text is a mere confirmation which may be skipped
+    is_status: bool,
+    /// how long to keep the text; forever if `None`
+    lifetime: Option<Duration>,
+}
+
+enum Outgoing {
+    Text {
+        text: OutgoingText,
+        reply_to: Option<MessageId>,
+    },
+    Embed {
+        embed: CreateEmbed,
+        components: CreateComponents,
+        sent: oneshot::Sender<Option<Message>>,
+    },
+    File {
+        text: String,
+        file_name: String,
+        data: Vec<u8>,
+    },
+}
+
+/// Sends the messages of a single channel one after another.
+///
+/// Texts arriving in quick succession are combined into a single message; while the channel is
+/// rate limited, they're held back (and combined) until Discord accepts messages again.
+struct Outbox {
+    channel: ChannelId,
+    http: Arc<Http>,
+    receiver: mpsc::UnboundedReceiver<Outgoing>,
+}
+
+impl Outbox {
+    fn spawn(channel: ChannelId, http: Arc<Http>) -> mpsc::UnboundedSender<Outgoing> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(
+            Self {
+                channel,
+                http,
+                receiver,
+            }
+            .run(),
+        );
+        sender
+    }
+
+    async fn run(mut self) {
+        let mut next = None;
+        loop {
+            let message = match next.take() {
+                Some(message) => message,
+                None => match self.receiver.recv().await {
+                    Some(message) => message,
+                    None => break,
+                },
+            };
+            match message {
+                Outgoing::Text { text, reply_to } => {
+                    let mut texts = vec![text];
+                    let window = COALESCE_WINDOW.max(self.rate_limit_delay().await);
+                    let deadline = Instant::now() + window;
+                    while let Ok(Some(message)) =
+                        tokio::time::timeout_at(deadline.into(), self.receiver.recv()).await
+                    {
+                        match message {
+                            Outgoing::Text {
+                                text,
+                                reply_to: None,
+                            } => texts.push(text),
+                            // replies, embeds and files need a message of their own
+                            message => {
+                                next = Some(message);
+                                break;
+                            }
+                        }
+                    }
+                    // only keep combined texts for a limited time if each of them would have been
+                    let lifetime = texts
+                        .iter()
+                        .map(|text| text.lifetime)
+                        .collect::<Option<Vec<_>>>()
+                        .and_then(|lifetimes| lifetimes.into_iter().max());
+                    let messages = self.send_text(&coalesce(texts), reply_to).await;
+                    if let Some(lifetime) = lifetime {
+                        self.delete_later(messages, lifetime);
+                    }
+                }
+                Outgoing::Embed {
+                    embed,
+                    components,
+                    sent,
+                } => {
+                    let message = self
+                        .send(|message| message.set_embed(embed).set_components(components))
+                        .await;
+                    let _ = sent.send(message);
                 }
-                if !msg.is_empty() {
-                    msg.push('\n');
+                Outgoing::File {
+                    text,
+                    file_name,
+                    data,
+                } => {
+                    self.send_file(&text, &file_name, data).await;
                 }
-                msg.push_str(line);
             }
+        }
+    }
 
-            if !msg.is_empty() {
-                Self::check_msg(channel.say(&http, msg).await);
-            }
+    /// How long Discord won't accept any more messages for this channel, according to the
+    /// rate limit headers of the previous response.
+    async fn rate_limit_delay(&self) -> Duration {
+        let routes = self.http.ratelimiter.routes();
+        let routes = routes.read().await;
+        let Some(ratelimit) = routes.get(&Route::ChannelsIdMessages(self.channel.0)) else {
+            return Duration::ZERO;
+        };
+        let ratelimit = ratelimit.lock().await;
+        if ratelimit.remaining() > 0 {
+            return Duration::ZERO;
         }
+        ratelimit
+            .reset()
+            .and_then(|reset| reset.duration_since(SystemTime::now()).ok())
+            .unwrap_or_default()
+    }
+
+    /// Returns the messages which have been sent.
+    async fn send_text(&self, text: &str, reply_to: Option<MessageId>) -> Vec<Message> {
+        let parts = split_message(text, MAX_MESSAGE_LENGTH);
+        if parts.len() > MAX_MESSAGE_PARTS {
+            // only happens if several long texts have been combined
+            return self
+                .send_file(TOO_LONG_NOTE, TOO_LONG_FILE_NAME, text.as_bytes().to_vec())
+                .await
+                .into_iter()
+                .collect();
+        }
+
+        let mut messages = Vec::new();
+        for (index, part) in parts.into_iter().enumerate() {
+            let reply_to = reply_to.filter(|_| index == 0);
+            let message = self
+                .send(|message| {
+                    message.content(part);
+                    if let Some(reply_to) = reply_to {
+                        message
+                            .reference_message((self.channel, reply_to))
+                            .allowed_mentions(|mentions| mentions.replied_user(false));
+                    }
+                    message
+                })
+                .await;
+            messages.extend(message);
+        }
+        messages
+    }
+
+    async fn send_file(&self, text: &str, file_name: &str, data: Vec<u8>) -> Option<Message> {
+        self.send(|message| {
+            message.content(text).add_file(AttachmentType::Bytes {
+                data: data.into(),
+                filename: file_name.to_owned(),
+            })
+        })
+        .await
+    }
+
+    fn delete_later(&self, messages: Vec<Message>, lifetime: Duration) {
+        let channel = self.channel;
+        let http = Arc::clone(&self.http);
+        tokio::spawn(async move {
+            tokio::time::sleep(lifetime).await;
+            for message in messages {
+                if let Err(err) = channel.delete_message(&http, message.id).await {
+                    eprintln!("failed to delete status message: {err}");
+                }
+            }
+        });
+    }
+
+    async fn send<'a, F>(&self, f: F) -> Option<Message>
+    where
+        for<'b> F: FnOnce(&'b mut CreateMessage<'a>) -> &'b mut CreateMessage<'a>,
+    {
+        Self::check_msg(self.channel.send_message(&self.http, f).await)
     }
 
     /// Checks that a message successfully sent; if not, then logs why to stdout.
-    fn check_msg(result: serenity::Result<Message>) {
-        if let Err(why) = result {
-            println!("Error sending message: {why:?}");
+    fn check_msg(result: serenity::Result<Message>) -> Option<Message> {
+        match result {
+            Ok(message) => Some(message),
+            Err(why) => {
+                println!("Error sending message: {why:?}");
+                None
+            }
+        }
+    }
+}
+
+/// Combines the texts into a single one; repeated texts are counted and excess status messages
+/// are skipped.
+fn coalesce(texts: Vec<OutgoingText>) -> String {
+    let mut merged: Vec<(String, bool, usize)> = Vec::new();
+    for OutgoingText {
+        text, is_status, ..
+    } in texts
+    {
+        match merged.last_mut() {
+            Some((last, _, count)) if *last == text => *count += 1,
+            _ => merged.push((text, is_status, 1)),
         }
     }
+
+    let status_count = merged.iter().filter(|(_, is_status, _)| *is_status).count();
+    let mut skip = status_count.saturating_sub(MAX_STATUS_MESSAGES);
+    let skipped = skip;
+    let mut lines = Vec::new();
+    for (text, is_status, count) in merged {
+        if is_status && skip > 0 {
+            skip -= 1;
+            if skip == 0 {
+                lines.push(format!("_({skipped} earlier status message(s) skipped)_"));
+            }
+            continue;
+        }
+        if count > 1 {
+            lines.push(format!("{text} (×{count})"));
+        } else {
+            lines.push(text);
+        }
+    }
+    lines.join("\n")
+}
+
+/// Splits the text into parts of at most `limit` bytes, preferably at line breaks.
+///
+/// Code blocks which have to be split are closed at the end of a part and reopened at the start
+/// of the next one, so they keep their formatting.
+fn split_message(text: &str, limit: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    // the line which opened the code block we're currently in (if any)
+    let mut open_fence: Option<String> = None;
+    // where the code block starts within the part and whether it got any lines in this part
+    let mut block_start = 0;
+    let mut block_has_lines = false;
+
+    for full_line in text.lines() {
+        let toggles_block = full_line.matches(CODE_FENCE).count() % 2 == 1;
+        // room to close the code block which is still open after this line
+        let closing_length = if open_fence.is_some() == toggles_block {
+            0
+        } else {
+            1 + CODE_FENCE.len()
+        };
+        if !part.is_empty() && part.len() + 1 + full_line.len() + closing_length > limit {
+            finish_part(
+                &mut parts,
+                &mut part,
+                open_fence.as_deref(),
+                block_start,
+                block_has_lines,
+            );
+            block_start = 0;
+            block_has_lines = false;
+        }
+
+        let mut line = full_line;
+        while part.len() + 1 + line.len() + closing_length > limit {
+            // the line itself is too long; split it at a character boundary
+            let split_closing_length = if open_fence.is_some() {
+                1 + CODE_FENCE.len()
+            } else {
+                0
+            };
+            let mut at = limit.saturating_sub(part.len() + 1 + split_closing_length);
+            while !line.is_char_boundary(at) {
+                at -= 1;
+            }
+            if at == 0 {
+                break;
+            }
+            if !part.is_empty() {
+                part.push('\n');
+            }
+            part.push_str(&line[..at]);
+            finish_part(
+                &mut parts,
+                &mut part,
+                open_fence.as_deref(),
+                block_start,
+                true,
+            );
+            block_start = 0;
+            block_has_lines = false;
+            line = &line[at..];
+        }
+
+        if toggles_block && open_fence.is_none() {
+            block_start = part.len();
+            block_has_lines = false;
+        }
+        if !part.is_empty() {
+            part.push('\n');
+        }
+        part.push_str(line);
+
+        if toggles_block {
+            open_fence = match open_fence {
+                Some(_) => None,
+                None => Some(fence_opener(full_line)),
+            };
+        } else if open_fence.is_some() {
+            block_has_lines = true;
+        }
+    }
+
+    if !part.trim().is_empty() {
+        parts.push(part);
+    }
+    parts
+}
+
+/// Moves the part to the finished ones and starts the next one, which reopens the code block the
+/// part ends in (if any).
+///
+/// The code block is closed if it got any lines in this part; otherwise it's left to the next
+/// part entirely, so no part holds an empty code block.
+fn finish_part(
+    parts: &mut Vec<String>,
+    part: &mut String,
+    open_fence: Option<&str>,
+    block_start: usize,
+    block_has_lines: bool,
+) {
+    if open_fence.is_some() {
+        if block_has_lines {
+            part.push('\n');
+            part.push_str(CODE_FENCE);
+        } else {
+            part.truncate(block_start);
+        }
+    }
+    if part.trim().is_empty() {
+        part.clear();
+    } else {
+        parts.push(std::mem::take(part));
+    }
+    if let Some(fence) = open_fence {
+        part.push_str(fence);
+    }
+}
+
+/// The fence to reopen a code block with; keeps the language of fences like ` ```rust `.
+fn fence_opener(line: &str) -> String {
+    let language = line
+        .trim_start()
+        .strip_prefix(CODE_FENCE)
+        .filter(|language| {
+            !language.is_empty() && language.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+        .unwrap_or_default();
+    format!("{CODE_FENCE}{language}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(text: &str) -> OutgoingText {
+        OutgoingText {
+            text: text.to_owned(),
+            is_status: true,
+            lifetime: None,
+        }
+    }
+
+    fn message(text: &str) -> OutgoingText {
+        OutgoingText {
+            is_status: false,
+            ..status(text)
+        }
+    }
+
+    fn assert_well_formed(parts: &[String], limit: usize) {
+        for part in parts {
+            assert!(part.len() <= limit, "part is too long: {:?}", part);
+            assert_eq!(part.matches(CODE_FENCE).count() % 2, 0, "{part:?}");
+            assert!(!part.contains("```\n```"), "empty code block: {:?}", part);
+        }
+    }
+
+    #[test]
+    fn short_texts_are_kept() {
+        assert_eq!(split_message("a\nb", 10), ["a\nb"]);
+        assert!(split_message("", 10).is_empty());
+    }
+
+    #[test]
+    fn texts_are_split_at_line_breaks() {
+        let parts = split_message("aaaa\nbbbb\ncccc", 10);
+        assert_eq!(parts, ["aaaa\nbbbb", "cccc"]);
+    }
+
+    #[test]
+    fn long_lines_are_split() {
+        let parts = split_message("aaaaaaaaaaaaaaa", 10);
+        assert_eq!(parts, ["aaaaaaaaa", "aaaaaa"]);
+        let parts = split_message("ääääää", 5);
+        assert_eq!(parts, ["ää", "ää", "ää"]);
+    }
+
+    #[test]
+    fn split_code_blocks_are_reopened() {
+        let parts = split_message("```rust\nlet a;\nlet b;\n```", 20);
+        assert_eq!(parts, ["```rust\nlet a;\n```", "```rust\nlet b;\n```"]);
+        assert_well_formed(&parts, 20);
+    }
+
+    #[test]
+    fn closing_fences_stay_with_their_block() {
+        let text = "```\naaaa\nbbbb\n```\ncccc";
+        for limit in 12..20 {
+            let parts = split_message(text, limit);
+            assert_well_formed(&parts, limit);
+            assert!(parts.iter().all(|part| part != "```\n```"), "{:?}", parts);
+        }
+        assert_eq!(split_message(text, 18), ["```\naaaa\nbbbb\n```", "cccc"]);
+    }
+
+    #[test]
+    fn empty_code_blocks_are_not_split_off() {
+        let text = format!("intro\n```rust\n{}\n```", "a".repeat(30));
+        let parts = split_message(&text, 20);
+        assert_eq!(parts[0], "intro");
+        assert!(parts[1].starts_with("```rust\na"), "{:?}", parts);
+        assert_well_formed(&parts, 20);
+
+        let parts = split_message(&format!("```\n{}\n```", "a".repeat(30)), 20);
+        assert!(parts[0].starts_with("```\na"), "{:?}", parts);
+        assert_well_formed(&parts, 20);
+    }
+
+    #[test]
+    fn fence_openers_keep_the_language() {
+        assert_eq!(fence_opener("```"), "```");
+        assert_eq!(fence_opener("```rust"), "```rust");
+        assert_eq!(fence_opener("  ```js"), "```js");
+        assert_eq!(fence_opener("```not a language"), "```");
+        assert_eq!(fence_opener("text ```"), "```");
+    }
+
+    #[test]
+    fn repeated_texts_are_coalesced() {
+        let texts = vec![message("a"), message("a"), message("b"), message("a")];
+        assert_eq!(coalesce(texts), "a (×2)\nb\na");
+    }
+
+    #[test]
+    fn old_status_messages_are_skipped() {
+        let texts = vec![
+            status("1"),
+            message("a"),
+            status("2"),
+            status("3"),
+            status("4"),
+            status("5"),
+        ];
+        assert_eq!(
+            coalesce(texts),
+            "a\n_(2 earlier status message(s) skipped)_\n3\n4\n5"
+        );
+    }
 }
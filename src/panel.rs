@@ -0,0 +1,389 @@
+use std::{sync::Arc, time::Duration};
+
+use serenity::{
+    builder::{CreateComponents, CreateEmbed},
+    http::Http,
+    model::{
+        application::component::ButtonStyle,
+        channel::ReactionType,
+        id::{ChannelId, MessageId},
+    },
+};
+
+use crate::{message::MessageChannel, track::Track};
+
+/// How often the progress of the current track will be updated.
+pub const PANEL_REFRESH_INTERVAL: Duration = Duration::from_secs(15);
+
+const PROGRESS_BAR_WIDTH: usize = 24;
+
+const COLOR_PLAYING: u32 = 0x0043_b581;
+const COLOR_PAUSED: u32 = 0x00fa_a61a;
+const COLOR_STOPPED: u32 = 0x0074_7f8d;
+
+/// Reactions which are added to the now-playing panel, so users only need to click them.
+const REACTIONS: [PanelAction; 5] = [
+    PanelAction::Next,
+    PanelAction::Pause,
+    PanelAction::Resume,
+    PanelAction::Stop,
+    PanelAction::Loop,
+];
+
+/// Buttons of the now-playing panel; identified by their component id.
+///
+/// Most of them can also be triggered by reacting to the panel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PanelAction {
+    Prev,
+    Pause,
+    Resume,
+    Play,
+    Next,
+    Stop,
+    Loop,
+}
+
+impl PanelAction {
+    pub fn custom_id(self) -> &'static str {
+        match self {
+            PanelAction::Prev => "panel-prev",
+            PanelAction::Pause => "panel-pause",
+            PanelAction::Resume => "panel-resume",
+            PanelAction::Play => "panel-play",
+            PanelAction::Next => "panel-next",
+            PanelAction::Stop => "panel-stop",
+            PanelAction::Loop => "panel-loop",
+        }
+    }
+
+    pub fn from_custom_id(custom_id: &str) -> Option<Self> {
+        [
+            PanelAction::Prev,
+            PanelAction::Pause,
+            PanelAction::Resume,
+            PanelAction::Play,
+            PanelAction::Next,
+            PanelAction::Stop,
+            PanelAction::Loop,
+        ]
+        .iter()
+        .copied()
+        .find(|action| action.custom_id() == custom_id)
+    }
+
+    pub fn from_reaction(emoji: &ReactionType) -> Option<Self> {
+        let ReactionType::Unicode(emoji) = emoji else {
+            return None;
+        };
+        // some clients append a variation selector
+        let emoji = emoji.trim_end_matches('\u{fe0f}');
+        REACTIONS
+            .iter()
+            .copied()
+            .find(|action| action.emoji() == emoji)
+    }
+
+    fn emoji(self) -> &'static str {
+        match self {
+            PanelAction::Prev => "⏮",
+            PanelAction::Pause => "⏸",
+            PanelAction::Resume | PanelAction::Play => "▶",
+            PanelAction::Next => "⏭",
+            PanelAction::Stop => "⏹",
+            PanelAction::Loop => "🔁",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Playback {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+/// Everything the panel shows; borrowed from the queue while rendering.
+pub struct PanelView<'a> {
+    pub playback: Playback,
+    /// the track at the current playback position (if any)
+    pub track: Option<&'a dyn Track>,
+    pub position: Option<Duration>,
+    /// 1-based position of the track within the queue
+    pub track_number: usize,
+    pub track_count: usize,
+    pub next_track: Option<&'a dyn Track>,
+    /// whether the current track will be repeated
+    pub looping: bool,
+}
+
+/// Rendered panel which can be sent or used to edit an existing panel.
+#[derive(Clone)]
+pub struct PanelContent {
+    embed: CreateEmbed,
+    components: CreateComponents,
+}
+
+impl PartialEq for PanelContent {
+    fn eq(&self, other: &Self) -> bool {
+        self.embed.0 == other.embed.0 && self.components.0 == other.components.0
+    }
+}
+
+impl PanelView<'_> {
+    pub fn render(&self) -> PanelContent {
+        PanelContent {
+            embed: self.render_embed(),
+            components: self.render_components(),
+        }
+    }
+
+    fn render_embed(&self) -> CreateEmbed {
+        let mut embed = CreateEmbed::default();
+        embed.colour(match self.playback {
+            Playback::Playing => COLOR_PLAYING,
+            Playback::Paused => COLOR_PAUSED,
+            Playback::Stopped => COLOR_STOPPED,
+        });
+
+        let Some(track) = self.track else {
+            embed.title("Nothing to play");
+            embed.description(
+                "You've reached the end of the queue. Use the `enqueue` command to add some tracks.",
+            );
+            return embed;
+        };
+
+        embed.title(
+            track
+                .title()
+                .unwrap_or_else(|| "(unknown title)".to_owned()),
+        );
+        embed.url(track.track_page_url());
+        embed.author(|author| {
+            author.name(
+                track
+                    .artist()
+                    .unwrap_or_else(|| "(unknown artist)".to_owned()),
+            );
+            if let Some(artist_url) = track.artist_url() {
+                author.url(artist_url);
+            }
+            author
+        });
+        if let Some(cover_url) = track.cover_url() {
+            embed.thumbnail(cover_url);
+        }
+
+        let state = match self.playback {
+            Playback::Playing => "▶ Playing",
+            Playback::Paused => "⏸ Paused",
+            Playback::Stopped => "⏹ Stopped",
+        };
+        let looping = if self.looping { " · 🔁 Looping" } else { "" };
+        let mut description = vec![format!("{state}{looping}"), self.progress()];
+        if let Some(comment) = track.comment() {
+            description.push(format!("> {comment}"));
+        }
+        embed.description(description.join("\n"));
+
+        let mut tags = Vec::new();
+        if let Some(genre) = track.genre() {
+            tags.push(genre);
+        }
+        if let Some(bpm) = track.bpm() {
+            tags.push(format!("{bpm:.0} bpm"));
+        }
+        if !tags.is_empty() {
+            embed.field("Style", tags.join(" @ "), true);
+        }
+        embed.field("Added by", format!("`@{}`", track.adding_user().name), true);
+        if let Some(remix_of) = track.remix_of() {
+            embed.field(
+                "Remix of",
+                format!(
+                    "[{} - {}]({})",
+                    remix_of.artist.as_deref().unwrap_or("(unknown artist)"),
+                    remix_of.title.as_deref().unwrap_or("(unknown title)"),
+                    remix_of.track_page_url
+                ),
+                false,
+            );
+        }
+        if let Some(stats) = track.stats() {
+            embed.field("Stats", stats, false);
+        }
+        if let Some(next_track) = self.next_track {
+            embed.field("Up next", next_track.caption(), false);
+        }
+        embed.footer(|footer| {
+            footer.text(format!(
+                "Track {} of {}",
+                self.track_number, self.track_count
+            ))
+        });
+        embed
+    }
+
+    /// e.g. `01:23 ━━━━━━●────────── 04:56`
+    fn progress(&self) -> String {
+        let position = self.position.unwrap_or_default().as_secs();
+        let Some(duration) = self
+            .track
+            .and_then(Track::duration)
+            .map(|duration| duration.as_secs())
+        else {
+            return format!("`{}` (unknown length)", format_time(position));
+        };
+
+        let position = position.min(duration);
+        #[allow(clippy::cast_possible_truncation)]
+        let shift = (PROGRESS_BAR_WIDTH as u64 * position)
+            .checked_div(duration)
+            .unwrap_or_default() as usize;
+        let shift = shift.min(PROGRESS_BAR_WIDTH - 1);
+        format!(
+            "`{} {}●{} {}`",
+            format_time(position),
+            "━".repeat(shift),
+            "─".repeat(PROGRESS_BAR_WIDTH - 1 - shift),
+            format_time(duration)
+        )
+    }
+
+    fn render_components(&self) -> CreateComponents {
+        let toggle = match self.playback {
+            Playback::Playing => PanelAction::Pause,
+            Playback::Paused => PanelAction::Resume,
+            Playback::Stopped => PanelAction::Play,
+        };
+        let buttons = [
+            (PanelAction::Prev, self.track_number <= 1),
+            (toggle, self.track.is_none()),
+            (PanelAction::Next, self.track.is_none()),
+            (PanelAction::Stop, self.playback == Playback::Stopped),
+            (PanelAction::Loop, self.track.is_none()),
+        ];
+
+        let mut components = CreateComponents::default();
+        components.create_action_row(|row| {
+            for (action, disabled) in buttons {
+                row.create_button(|button| {
+                    button
+                        .custom_id(action.custom_id())
+                        .emoji(ReactionType::Unicode(action.emoji().to_owned()))
+                        .style(if action == PanelAction::Loop && self.looping {
+                            ButtonStyle::Success
+                        } else {
+                            ButtonStyle::Secondary
+                        })
+                        .disabled(disabled)
+                });
+            }
+            row
+        });
+        components
+    }
+}
+
+fn format_time(secs: u64) -> String {
+    format!("{:0>2}:{:0>2}", secs / 60, secs % 60)
+}
+
+struct PanelMessage {
+    channel_id: ChannelId,
+    message_id: MessageId,
+    http: Arc<Http>,
+    /// what the message currently shows; unchanged panels won't be edited
+    content: PanelContent,
+}
+
+/// The guild's now-playing message which is being edited instead of posting a new one for every
+/// change.
+#[derive(Default)]
+pub struct NowPlayingPanel {
+    message: Option<PanelMessage>,
+}
+
+impl NowPlayingPanel {
+    /// Shows the panel in the given channel; an existing panel in the same channel is updated.
+    pub async fn show(&mut self, out: &MessageChannel, content: &PanelContent) {
+        let is_same_channel = self
+            .message
+            .as_ref()
+            .is_some_and(|message| Some(message.channel_id) == out.channel_id());
+        if is_same_channel && self.refresh(content).await {
+            return;
+        }
+        self.repost(out, content).await;
+    }
+
+    /// Replaces the existing panel with a new one at the bottom of the given channel.
+    pub async fn repost(&mut self, out: &MessageChannel, content: &PanelContent) {
+        self.remove().await;
+
+        let (Some(message), Some(http)) = (
+            out.send_embed(content.embed.clone(), content.components.clone())
+                .await,
+            out.http(),
+        ) else {
+            return;
+        };
+        let reacting_http = Arc::clone(http);
+        let reacting_message = message.clone();
+        tokio::spawn(async move {
+            for action in REACTIONS {
+                let reaction = ReactionType::Unicode(action.emoji().to_owned());
+                if let Err(err) = reacting_message.react(&reacting_http, reaction).await {
+                    eprintln!("failed to add reaction controls: {err}");
+                    break;
+                }
+            }
+        });
+        self.message = Some(PanelMessage {
+            channel_id: message.channel_id,
+            message_id: message.id,
+            http: Arc::clone(http),
+            content: content.clone(),
+        });
+    }
+
+    /// Updates the existing panel; returns `false` if there is none (anymore).
+    pub async fn refresh(&mut self, content: &PanelContent) -> bool {
+        let Some(message) = &mut self.message else {
+            return false;
+        };
+        if message.content == *content {
+            return true;
+        }
+        let result = message
+            .channel_id
+            .edit_message(&message.http, message.message_id, |edit| {
+                edit.set_embed(content.embed.clone())
+                    .set_components(content.components.clone())
+            })
+            .await;
+        if let Err(err) = result {
+            // most likely somebody deleted the panel
+            eprintln!("failed to update the now-playing panel: {err}");
+            self.message = None;
+            return false;
+        }
+        message.content = content.clone();
+        true
+    }
+
+    /// The message currently showing the panel.
+    pub fn message_id(&self) -> Option<MessageId> {
+        self.message.as_ref().map(|message| message.message_id)
+    }
+
+    pub async fn remove(&mut self) {
+        if let Some(message) = self.message.take() {
+            let _ = message
+                .channel_id
+                .delete_message(&message.http, message.message_id)
+                .await;
+        }
+    }
+}
@@ -0,0 +1,557 @@
+use lazy_static::lazy_static;
+use std::{
+    collections::VecDeque,
+    convert::TryFrom,
+    fs,
+    sync::Mutex as StdMutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{params, Connection, Row};
+use serenity::{
+    builder::CreateEmbed,
+    model::id::{GuildId, UserId},
+};
+
+use crate::{storage, track::Track};
+
+/// Number of plays which are kept in memory per guild; all of them are stored in the database.
+const MAX_HISTORY_LENGTH: usize = 200;
+
+/// Discord doesn't allow more fields per embed.
+pub const MAX_SHOWN_PLAYS: usize = 25;
+
+/// Field names are cut down, so more plays fit into the embed.
+const MAX_NAME_LENGTH: usize = 120;
+
+/// Discord rejects embeds whose texts are longer than this in total.
+const MAX_EMBED_LENGTH: usize = 6000;
+
+/// Room left for the footer; e.g. `Showing 25 of the last 200 play(s)`
+const MAX_FOOTER_LENGTH: usize = 64;
+
+const HISTORY_TITLE: &str = "Recently played";
+
+const COLOR_HISTORY: u32 = 0x0074_7f8d;
+
+/// Database within the data directory which contains the finished plays of all guilds.
+const DATABASE_FILE_NAME: &str = "plays.sqlite";
+
+/// Columns of the `plays` table in the order `PlayRecord::from_row` expects them.
+const PLAY_COLUMNS: &str = "artist, title, genre, url, added_by, added_by_name, started_at, \
+    ended_at, paused_secs, listeners, outcome, skipped_by";
+
+lazy_static! {
+    /// Shared by all guilds; it's opened on first use and only ever used on blocking threads.
+    static ref DATABASE: StdMutex<Option<Connection>> = StdMutex::default();
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlayOutcome {
+    Playing,
+    /// played until its end
+    Completed,
+    /// ended early; `None` if the bot ended it on its own (e.g. when leaving)
+    Skipped {
+        by: Option<UserId>,
+    },
+}
+
+/// A track which has been played (or is still playing).
+#[derive(Clone, Debug)]
+pub struct PlayRecord {
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub genre: Option<String>,
+    pub track_page_url: String,
+    pub added_by: UserId,
+    pub added_by_name: String,
+    /// seconds since the unix epoch
+    pub started_at: u64,
+    /// seconds since the unix epoch
+    pub ended_at: Option<u64>,
+    /// how long the track has been paused in total; doesn't include an ongoing pause
+    pub paused_secs: u64,
+    /// seconds since the unix epoch; set while the track is paused
+    paused_since: Option<u64>,
+    /// number of users in the voice channel when the track started
+    pub listeners: usize,
+    pub outcome: PlayOutcome,
+}
+
+impl PlayRecord {
+    fn new(track: &dyn Track, listeners: usize) -> Self {
+        Self {
+            artist: track.artist(),
+            title: track.title(),
+            genre: track.genre(),
+            track_page_url: track.track_page_url().to_owned(),
+            added_by: track.adding_user().id,
+            added_by_name: track.adding_user().name.clone(),
+            started_at: unix_time_now(),
+            ended_at: None,
+            paused_secs: 0,
+            paused_since: None,
+            listeners,
+            outcome: PlayOutcome::Playing,
+        }
+    }
+
+    /// e.g. `artist - title`
+    pub fn caption(&self) -> String {
+        format!(
+            "{} - {}",
+            self.artist.as_deref().unwrap_or("(unknown artist)"),
+            self.title.as_deref().unwrap_or("(unknown title)")
+        )
+    }
+
+    /// How long the track has been played; pauses don't count.
+    pub fn played_secs(&self) -> u64 {
+        self.ended_at.map_or(0, |ended_at| {
+            ended_at
+                .saturating_sub(self.started_at)
+                .saturating_sub(self.paused_secs)
+        })
+    }
+
+    /// Adds an ongoing pause to the paused time.
+    fn end_pause(&mut self, now: u64) {
+        if let Some(paused_since) = self.paused_since.take() {
+            self.paused_secs += now.saturating_sub(paused_since);
+        }
+    }
+
+    fn insert(&self, db: &Connection, guild_id: GuildId) -> rusqlite::Result<()> {
+        let (outcome, skipped_by) = match self.outcome {
+            PlayOutcome::Playing => ("playing", None),
+            PlayOutcome::Completed => ("completed", None),
+            PlayOutcome::Skipped { by } => ("skipped", by),
+        };
+        db.execute(
+            &format!(
+                "INSERT INTO plays (guild_id, {PLAY_COLUMNS}) \
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)"
+            ),
+            params![
+                guild_id.0,
+                self.artist,
+                self.title,
+                self.genre,
+                self.track_page_url,
+                self.added_by.0,
+                self.added_by_name,
+                self.started_at,
+                self.ended_at,
+                self.paused_secs,
+                self.listeners,
+                outcome,
+                skipped_by.map(|user_id| user_id.0),
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let outcome = match row.get::<_, String>(10)?.as_str() {
+            "completed" => PlayOutcome::Completed,
+            _ => PlayOutcome::Skipped {
+                by: row.get::<_, Option<u64>>(11)?.map(UserId),
+            },
+        };
+        Ok(Self {
+            artist: row.get(0)?,
+            title: row.get(1)?,
+            genre: row.get(2)?,
+            track_page_url: row.get(3)?,
+            added_by: UserId(row.get(4)?),
+            added_by_name: row.get(5)?,
+            started_at: row.get(6)?,
+            ended_at: row.get(7)?,
+            paused_secs: row.get(8)?,
+            paused_since: None,
+            listeners: row.get(9)?,
+            outcome,
+        })
+    }
+}
+
+fn create_schema(db: &Connection) -> rusqlite::Result<()> {
+    db.execute_batch(
+        "CREATE TABLE IF NOT EXISTS plays (
+            id INTEGER PRIMARY KEY,
+            guild_id INTEGER NOT NULL,
+            artist TEXT,
+            title TEXT,
+            genre TEXT,
+            url TEXT NOT NULL,
+            added_by INTEGER NOT NULL,
+            added_by_name TEXT NOT NULL,
+            started_at INTEGER NOT NULL,
+            ended_at INTEGER,
+            paused_secs INTEGER NOT NULL,
+            listeners INTEGER NOT NULL,
+            outcome TEXT NOT NULL,
+            skipped_by INTEGER
+        );
+        CREATE INDEX IF NOT EXISTS plays_by_guild_and_start ON plays (guild_id, started_at);",
+    )
+}
+
+fn open_database() -> Result<Connection, String> {
+    let dir = storage::data_dir();
+    fs::create_dir_all(&dir).map_err(|err| err.to_string())?;
+    let db = Connection::open(dir.join(DATABASE_FILE_NAME)).map_err(|err| err.to_string())?;
+    create_schema(&db).map_err(|err| err.to_string())?;
+    Ok(db)
+}
+
+/// Runs the query on a blocking thread, so the guild's worker isn't held up by the disk.
+async fn with_database<T, F>(query: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || {
+        let mut database = DATABASE.lock().expect("failed to lock the play database");
+        let db = match database.take() {
+            Some(db) => db,
+            None => open_database()?,
+        };
+        query(database.get_or_insert(db)).map_err(|err| err.to_string())
+    })
+    .await
+    .map_err(|err| err.to_string())?
+}
+
+/// The latest plays of the guild; the most recent one comes last.
+fn recent_plays(
+    db: &Connection,
+    guild_id: GuildId,
+    count: usize,
+) -> rusqlite::Result<Vec<PlayRecord>> {
+    let mut statement = db.prepare(&format!(
+        "SELECT {PLAY_COLUMNS} FROM plays WHERE guild_id = ?1 \
+        ORDER BY started_at DESC, id DESC LIMIT ?2"
+    ))?;
+    let mut plays = statement
+        .query_map(params![guild_id.0, count], PlayRecord::from_row)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    plays.reverse();
+    Ok(plays)
+}
+
+/// Plays of the guild which started within the given seconds since the unix epoch; the end is
+/// exclusive.
+fn plays_between(
+    db: &Connection,
+    guild_id: GuildId,
+    start: u64,
+    end: u64,
+) -> rusqlite::Result<Vec<PlayRecord>> {
+    let mut statement = db.prepare(&format!(
+        "SELECT {PLAY_COLUMNS} FROM plays WHERE guild_id = ?1 \
+        AND started_at >= ?2 AND started_at < ?3 ORDER BY started_at, id"
+    ))?;
+    // SQLite only knows signed integers
+    let end = i64::try_from(end).unwrap_or(i64::MAX);
+    let plays = statement
+        .query_map(params![guild_id.0, start, end], PlayRecord::from_row)?
+        .collect();
+    plays
+}
+
+/// The guild's most recent plays; every finished play is also stored in the database.
+pub struct PlayHistory {
+    guild_id: GuildId,
+    plays: VecDeque<PlayRecord>,
+}
+
+impl PlayHistory {
+    pub fn new(guild_id: GuildId) -> Self {
+        Self {
+            guild_id,
+            plays: VecDeque::new(),
+        }
+    }
+
+    /// Picks up the most recent plays from the database; e.g. after a restart.
+    pub async fn load(&mut self) {
+        let guild_id = self.guild_id;
+        match with_database(move |db| recent_plays(db, guild_id, MAX_HISTORY_LENGTH)).await {
+            Ok(plays) => self.plays = plays.into(),
+            Err(err) => eprintln!("failed to read the plays of guild {guild_id}: {err}"),
+        }
+    }
+
+    /// Finished plays which started within the given seconds since the unix epoch; the end is
+    /// exclusive.
+    pub async fn read_period(&self, start: u64, end: u64) -> Result<Vec<PlayRecord>, String> {
+        let guild_id = self.guild_id;
+        with_database(move |db| plays_between(db, guild_id, start, end)).await
+    }
+
+    /// Records the start of a track; a track which is still playing is considered interrupted.
+    pub fn start(&mut self, track: &dyn Track, listeners: usize) {
+        self.finish(PlayOutcome::Skipped { by: None });
+        self.plays.push_back(PlayRecord::new(track, listeners));
+        if self.plays.len() > MAX_HISTORY_LENGTH {
+            self.plays.pop_front();
+        }
+    }
+
+    /// Records that the track which is currently playing (if any) has been paused.
+    pub fn pause(&mut self) {
+        if let Some(play) = self.playing() {
+            play.paused_since.get_or_insert_with(unix_time_now);
+        }
+    }
+
+    /// Records that the track which is currently playing (if any) has been resumed.
+    pub fn resume(&mut self) {
+        if let Some(play) = self.playing() {
+            play.end_pause(unix_time_now());
+        }
+    }
+
+    fn playing(&mut self) -> Option<&mut PlayRecord> {
+        self.plays
+            .back_mut()
+            .filter(|play| play.outcome == PlayOutcome::Playing)
+    }
+
+    /// Records the end of the track which is currently playing (if any).
+    pub fn finish(&mut self, outcome: PlayOutcome) {
+        let Some(play) = self
+            .plays
+            .back_mut()
+            .filter(|play| play.outcome == PlayOutcome::Playing)
+        else {
+            return;
+        };
+        let now = unix_time_now();
+        play.end_pause(now);
+        play.outcome = outcome;
+        play.ended_at = Some(now);
+
+        let play = play.clone();
+        let guild_id = self.guild_id;
+        tokio::spawn(async move {
+            if let Err(err) = with_database(move |db| play.insert(db, guild_id)).await {
+                eprintln!("failed to store a play of guild {guild_id}: {err}");
+            }
+        });
+    }
+
+    /// Lists the latest plays; the most recent one comes first.
+    pub fn render(&self, count: usize) -> CreateEmbed {
+        let mut embed = CreateEmbed::default();
+        embed.title(HISTORY_TITLE).colour(COLOR_HISTORY);
+        if self.plays.is_empty() {
+            embed.description("Nothing has been played yet.");
+            return embed;
+        }
+
+        // stop adding plays before the embed exceeds Discord's limit
+        let mut length = HISTORY_TITLE.len() + MAX_FOOTER_LENGTH;
+        let mut shown = 0;
+        for play in self.plays.iter().rev().take(count.min(MAX_SHOWN_PLAYS)) {
+            let name: String = play.caption().chars().take(MAX_NAME_LENGTH).collect();
+
+            let outcome = match &play.outcome {
+                PlayOutcome::Playing => "▶ playing".to_owned(),
+                PlayOutcome::Completed => "✅ completed".to_owned(),
+                PlayOutcome::Skipped { by: Some(user_id) } => format!("⏭ skipped by <@{user_id}>"),
+                PlayOutcome::Skipped { by: None } => "⏹ interrupted".to_owned(),
+            };
+            let played = if play.ended_at.is_some() {
+                let secs = play.played_secs();
+                format!(" after {}:{:0>2}", secs / 60, secs % 60)
+            } else {
+                String::new()
+            };
+            let mut details = vec![
+                format!("<t:{}:f>", play.started_at),
+                format!("{outcome}{played}"),
+                format!("👥 {}", play.listeners),
+            ];
+            if let Some(genre) = &play.genre {
+                details.push(format!("[{genre}]"));
+            }
+            details.push(format!(
+                "added by `@{}` · <{}>",
+                play.added_by_name, play.track_page_url
+            ));
+            let details = details.join(" · ");
+            length += name.chars().count() + details.chars().count();
+            if length > MAX_EMBED_LENGTH {
+                break;
+            }
+            embed.field(name, details, false);
+            shown += 1;
+        }
+
+        embed.footer(|footer| {
+            footer.text(format!(
+                "Showing {shown} of the last {} play(s)",
+                self.plays.len()
+            ))
+        });
+        embed
+    }
+}
+
+/// Seconds since the unix epoch.
+pub fn unix_time_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(title: &str, started_at: u64, outcome: PlayOutcome) -> PlayRecord {
+        PlayRecord {
+            artist: Some("artist".to_owned()),
+            title: Some(title.to_owned()),
+            genre: None,
+            track_page_url: format!("https://www.audiotool.com/track/{title}"),
+            added_by: UserId(1),
+            added_by_name: "user".to_owned(),
+            started_at,
+            ended_at: Some(started_at + 100),
+            paused_secs: 10,
+            paused_since: None,
+            listeners: 3,
+            outcome,
+        }
+    }
+
+    fn database(plays: &[PlayRecord]) -> Connection {
+        let db = Connection::open_in_memory().unwrap();
+        create_schema(&db).unwrap();
+        for play in plays {
+            play.insert(&db, GuildId(1)).unwrap();
+        }
+        db
+    }
+
+    fn titles(plays: &[PlayRecord]) -> Vec<&str> {
+        plays
+            .iter()
+            .map(|play| play.title.as_deref().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn plays_survive_the_database() {
+        let completed = record("a", 1000, PlayOutcome::Completed);
+        let skipped = PlayRecord {
+            artist: None,
+            genre: Some("genre".to_owned()),
+            ..record(
+                "b",
+                2000,
+                PlayOutcome::Skipped {
+                    by: Some(UserId(2)),
+                },
+            )
+        };
+        let skipped_by_the_bot = record("c", 3000, PlayOutcome::Skipped { by: None });
+        let db = database(&[completed, skipped, skipped_by_the_bot]);
+
+        let plays = recent_plays(&db, GuildId(1), 10).unwrap();
+        assert_eq!(titles(&plays), ["a", "b", "c"]);
+        assert_eq!(plays[0].outcome, PlayOutcome::Completed);
+        assert_eq!(plays[0].artist.as_deref(), Some("artist"));
+        assert_eq!(plays[0].ended_at, Some(1100));
+        assert_eq!(plays[0].paused_secs, 10);
+        assert_eq!(plays[0].played_secs(), 90);
+        assert_eq!(plays[0].listeners, 3);
+        assert_eq!(
+            plays[1].outcome,
+            PlayOutcome::Skipped {
+                by: Some(UserId(2))
+            }
+        );
+        assert_eq!(plays[1].artist, None);
+        assert_eq!(plays[1].genre.as_deref(), Some("genre"));
+        assert_eq!(plays[2].outcome, PlayOutcome::Skipped { by: None });
+    }
+
+    #[test]
+    fn recent_plays_are_the_latest_in_order() {
+        let db = database(&[
+            record("b", 2000, PlayOutcome::Completed),
+            record("a", 1000, PlayOutcome::Completed),
+            record("c", 3000, PlayOutcome::Completed),
+        ]);
+        record("other guild", 4000, PlayOutcome::Completed)
+            .insert(&db, GuildId(2))
+            .unwrap();
+
+        let plays = recent_plays(&db, GuildId(1), 2).unwrap();
+        assert_eq!(titles(&plays), ["b", "c"]);
+    }
+
+    #[test]
+    fn plays_between_only_returns_the_period() {
+        let db = database(&[
+            record("a", 1000, PlayOutcome::Completed),
+            record("b", 2000, PlayOutcome::Completed),
+            record("c", 3000, PlayOutcome::Completed),
+        ]);
+
+        let plays = plays_between(&db, GuildId(1), 2000, 3000).unwrap();
+        assert_eq!(titles(&plays), ["b"]);
+        let plays = plays_between(&db, GuildId(1), 0, u64::MAX).unwrap();
+        assert_eq!(titles(&plays), ["a", "b", "c"]);
+        let plays = plays_between(&db, GuildId(2), 0, u64::MAX).unwrap();
+        assert!(plays.is_empty());
+    }
+
+    #[test]
+    fn long_histories_fit_into_an_embed() {
+        let mut history = PlayHistory::new(GuildId(1));
+        for index in 0..MAX_SHOWN_PLAYS {
+            let title = format!("{index} {}", "very long title ".repeat(20));
+            history.plays.push_back(PlayRecord {
+                artist: Some("a rather long artist name ".repeat(4)),
+                genre: Some("some genre".to_owned()),
+                track_page_url: format!("https://www.audiotool.com/track/{}/", "x".repeat(100)),
+                added_by_name: "u".repeat(32),
+                ..record(
+                    &title,
+                    1000,
+                    PlayOutcome::Skipped {
+                        by: Some(UserId(2)),
+                    },
+                )
+            });
+        }
+
+        let embed = history.render(MAX_SHOWN_PLAYS);
+        let fields = embed.0["fields"].as_array().expect("no fields");
+        assert!(!fields.is_empty());
+        assert!(fields.len() < MAX_SHOWN_PLAYS);
+        let text_length = |value: &serde_json::Value| value.as_str().unwrap().chars().count();
+        let length = text_length(&embed.0["title"])
+            + text_length(&embed.0["footer"]["text"])
+            + fields
+                .iter()
+                .map(|field| text_length(&field["name"]) + text_length(&field["value"]))
+                .sum::<usize>();
+        assert!(length <= MAX_EMBED_LENGTH, "embed is too long: {}", length);
+        let footer = embed.0["footer"]["text"].as_str().unwrap();
+        assert_eq!(
+            footer,
+            format!(
+                "Showing {} of the last {MAX_SHOWN_PLAYS} play(s)",
+                fields.len()
+            )
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use std::{path::PathBuf, time::Duration};
+
+use json::JsonValue;
+use serenity::model::id::{GuildId, UserId};
+use tokio::sync::OwnedMutexGuard;
+
+use crate::{
+    command::{CommandError, CommandResult},
+    storage,
+};
+
+/// File within the user's (or guild's) data directory which contains all of their playlists.
+const PLAYLISTS_FILE_NAME: &str = "playlists.json";
+
+/// A track within a playlist; it will be resolved again when the playlist is loaded.
+#[derive(Clone, Debug)]
+pub struct PlaylistEntry {
+    pub url: String,
+    pub comment: Option<String>,
+    /// `artist – title` at the time the track has been added
+    pub caption: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Playlist {
+    pub name: String,
+    /// creator of a guild's playlist; personal playlists belong to the user anyway
+    pub owner: Option<UserId>,
+    pub entries: Vec<PlaylistEntry>,
+}
+
+impl Playlist {
+    /// References to be enqueued; just like they were given to the `+` command.
+    pub fn track_refs(&self) -> Vec<(String, Option<String>)> {
+        self.entries
+            .iter()
+            .map(|entry| (entry.url.clone(), entry.comment.clone()))
+            .collect()
+    }
+
+    /// Total length of all tracks and whether the length of some tracks is unknown.
+    pub fn total_duration(&self) -> (Duration, bool) {
+        self.entries.iter().fold(
+            (Duration::ZERO, false),
+            |(total, unsure), entry| match entry.duration {
+                Some(duration) => (total + duration, unsure),
+                None => (total, true),
+            },
+        )
+    }
+
+    /// Moves the entry at `from` to `to`; both 0-based.
+    pub fn move_entry(&mut self, from: usize, to: usize) -> CommandResult {
+        let track_count = self.entries.len();
+        if from >= track_count || to >= track_count {
+            return Err(CommandError::Execution(format!(
+                "The playlist `{}` only contains {track_count} track(s).",
+                self.name
+            )));
+        }
+        let entry = self.entries.remove(from);
+        self.entries.insert(to, entry);
+        Ok(())
+    }
+
+    fn to_json(&self) -> JsonValue {
+        json::object! {
+            name: self.name.clone(),
+            // ids exceed the precision of some JSON parsers
+            owner: self.owner.map(|user_id| user_id.to_string()),
+            tracks: self.entries.iter().map(|entry| json::object! {
+                url: entry.url.clone(),
+                comment: entry.comment.clone(),
+                caption: entry.caption.clone(),
+                duration: entry.duration.map(|duration| duration.as_secs()),
+            }).collect::<Vec<_>>(),
+        }
+    }
+
+    fn from_json(playlist: &JsonValue) -> Option<Self> {
+        Some(Self {
+            name: playlist["name"].as_str()?.to_owned(),
+            owner: playlist["owner"]
+                .as_str()
+                .and_then(|user_id| user_id.parse().ok())
+                .map(UserId),
+            entries: playlist["tracks"]
+                .members()
+                .filter_map(|entry| {
+                    Some(PlaylistEntry {
+                        url: entry["url"].as_str()?.to_owned(),
+                        comment: entry["comment"].as_str().map(ToOwned::to_owned),
+                        caption: entry["caption"].as_str().map(ToOwned::to_owned),
+                        duration: entry["duration"].as_u64().map(Duration::from_secs),
+                    })
+                })
+                .collect(),
+        })
+    }
+}
+
+/// A user's personal playlists or a guild's shared playlists; persisted as a single JSON document.
+pub struct PlaylistStore {
+    path: PathBuf,
+    playlists: Vec<Playlist>,
+    /// a user's playlists might be changed from several guilds at once, so they stay locked while
+    /// the store is open; a guild's playlists are only ever changed by the guild's worker
+    _lock: Option<OwnedMutexGuard<()>>,
+}
+
+impl PlaylistStore {
+    pub async fn open_personal(user_id: UserId) -> CommandResult<Self> {
+        let path = storage::user_dir(user_id).join(PLAYLISTS_FILE_NAME);
+        let lock = storage::lock_document(&path).await;
+        Self::open(path, Some(lock)).await
+    }
+
+    pub async fn open_shared(guild_id: GuildId) -> CommandResult<Self> {
+        Self::open(storage::guild_dir(guild_id).join(PLAYLISTS_FILE_NAME), None).await
+    }
+
+    /// The file is read on a blocking thread, so the guild's worker isn't held up by the disk.
+    async fn open(path: PathBuf, lock: Option<OwnedMutexGuard<()>>) -> CommandResult<Self> {
+        let read_path = path.clone();
+        let document = tokio::task::spawn_blocking(move || storage::read_document(&read_path))
+            .await
+            .map_err(|err| err.to_string())
+            .and_then(|document| document.map_err(|err| err.to_string()))
+            .map_err(|err| {
+                CommandError::Execution(format!("Failed to read the playlists: {err}"))
+            })?;
+        let playlists = document
+            .map(|document| {
+                document["playlists"]
+                    .members()
+                    .filter_map(Playlist::from_json)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(Self {
+            path,
+            playlists,
+            _lock: lock,
+        })
+    }
+
+    /// The file is written on a blocking thread, just like it's read.
+    pub async fn save(&self) -> CommandResult {
+        let document = json::object! {
+            playlists: self.playlists.iter().map(Playlist::to_json).collect::<Vec<_>>(),
+        };
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || storage::write_document(&path, &document))
+            .await
+            .map_err(|err| err.to_string())
+            .and_then(|result| result.map_err(|err| err.to_string()))
+            .map_err(|err| CommandError::Execution(format!("Failed to save the playlists: {err}")))
+    }
+
+    /// One line per playlist; `None` if there are no playlists.
+    pub fn describe(&self) -> Option<String> {
+        if self.playlists.is_empty() {
+            return None;
+        }
+        let lines: Vec<String> = self
+            .playlists
+            .iter()
+            .map(|playlist| {
+                format!(
+                    "· `{}` - {} track(s)",
+                    playlist.name,
+                    playlist.entries.len()
+                )
+            })
+            .collect();
+        Some(lines.join("\n"))
+    }
+
+    /// Names are case-insensitive.
+    pub fn find(&self, name: &str) -> CommandResult<&Playlist> {
+        self.playlists
+            .iter()
+            .find(|playlist| playlist.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| CommandError::Execution(format!("There's no playlist named `{name}`.")))
+    }
+
+    pub fn find_mut(&mut self, name: &str) -> CommandResult<&mut Playlist> {
+        self.playlists
+            .iter_mut()
+            .find(|playlist| playlist.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| CommandError::Execution(format!("There's no playlist named `{name}`.")))
+    }
+
+    /// Adds the playlist; an existing playlist of the same name will be replaced.
+    pub fn put(&mut self, playlist: Playlist) {
+        self.playlists
+            .retain(|existing| !existing.name.eq_ignore_ascii_case(&playlist.name));
+        self.playlists.push(playlist);
+    }
+
+    pub fn remove(&mut self, name: &str) -> CommandResult<Playlist> {
+        let index = self
+            .playlists
+            .iter()
+            .position(|playlist| playlist.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                CommandError::Execution(format!("There's no playlist named `{name}`."))
+            })?;
+        Ok(self.playlists.remove(index))
+    }
+}
@@ -0,0 +1,109 @@
+use lazy_static::lazy_static;
+use std::{collections::BTreeMap, sync::Mutex as StdMutex, time::Duration};
+
+use serenity::{
+    client::bridge::gateway::ShardMessenger,
+    model::{gateway::Activity, id::GuildId},
+};
+
+/// How long each guild's track is shown while several guilds are playing at once.
+const ROTATION_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Discord cuts off longer activity names anyway.
+const MAX_ACTIVITY_LENGTH: usize = 128;
+
+lazy_static! {
+    static ref PRESENCE: StdMutex<Presence> = StdMutex::default();
+}
+
+/// The bot's activity ("Listening to …") which follows whatever is being played.
+#[derive(Default)]
+struct Presence {
+    /// becomes available once the gateway is ready
+    shard: Option<ShardMessenger>,
+    /// what each guild is currently playing
+    playing: BTreeMap<GuildId, String>,
+    /// position within `playing` which is shown while rotating
+    rotation: usize,
+    /// whether a task is rotating through the guilds
+    is_rotating: bool,
+}
+
+/// Remembers the connection to the gateway; the presence is sent through it.
+pub fn set_shard(shard: ShardMessenger) {
+    let mut presence = lock();
+    presence.shard = Some(shard);
+    presence.update();
+}
+
+/// Shows the track a guild has just started; e.g. `artist – title`.
+pub fn set_playing(guild_id: GuildId, track: String) {
+    let mut presence = lock();
+    if presence.playing.get(&guild_id) == Some(&track) {
+        return;
+    }
+    presence.playing.insert(guild_id, track);
+    presence.update();
+}
+
+/// Stops showing the guild's track after the playback has been stopped.
+pub fn reset(guild_id: GuildId) {
+    let mut presence = lock();
+    if presence.playing.remove(&guild_id).is_some() {
+        presence.update();
+    }
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Presence> {
+    PRESENCE
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+impl Presence {
+    fn update(&mut self) {
+        let Some(shard) = &self.shard else {
+            return;
+        };
+
+        let guild_count = self.playing.len();
+        let Some(track) = self
+            .playing
+            .values()
+            .nth(self.rotation % guild_count.max(1))
+        else {
+            shard.set_activity(None);
+            return;
+        };
+        let name = if guild_count > 1 {
+            format!(
+                "{track} · {} of {guild_count} servers",
+                self.rotation % guild_count + 1
+            )
+        } else {
+            track.clone()
+        };
+        let name: String = name.chars().take(MAX_ACTIVITY_LENGTH).collect();
+        shard.set_activity(Some(Activity::listening(name)));
+
+        if guild_count > 1 && !self.is_rotating {
+            self.is_rotating = true;
+            tokio::spawn(rotate());
+        }
+    }
+}
+
+/// Shows the guilds' tracks one after another for as long as more than one guild is playing.
+async fn rotate() {
+    loop {
+        tokio::time::sleep(ROTATION_INTERVAL).await;
+        let mut presence = lock();
+        if presence.playing.len() <= 1 {
+            presence.is_rotating = false;
+            presence.rotation = 0;
+            return;
+        }
+        presence.rotation = presence.rotation.wrapping_add(1);
+        presence.update();
+    }
+}
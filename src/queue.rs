@@ -1,14 +1,27 @@
 use std::{collections::HashMap, iter::FromIterator, sync::Arc, time::Duration};
 
 use serenity::{
+    builder::CreateComponents,
     http::Http,
-    model::id::{ChannelId, GuildId, UserId},
+    model::id::{ChannelId, GuildId, MessageId, UserId},
+};
+use songbird::{
+    input::Restartable,
+    tracks::{PlayMode, TrackHandle},
+    Call, CoreEvent, Event, EventContext, TrackEvent,
 };
-use songbird::{input::Restartable, tracks::TrackHandle, Call, Event, EventContext, TrackEvent};
 
 use crate::{
     command::{CommandError, CommandResult},
     message::MessageChannel,
+    panel::{NowPlayingPanel, PanelContent, PanelView, Playback},
+    play_history::{self, PlayHistory, PlayOutcome},
+    playlist::{Playlist, PlaylistEntry, PlaylistStore},
+    presence,
+    queue_history::{CommandId, QueueEdit, QueueEditKind, QueueHistory, QueueSnapshot, TrackId},
+    queue_page::{QueueEntry, QueuePage, QueuePageView, QUEUE_PAGE_SIZE},
+    stage::Stage,
+    stats::{self, StatsCategory, StatsPeriod},
     track::{
         selection::{IndexResolve, TrackIndex, TrackIndexSelection},
         Track,
@@ -38,7 +51,7 @@ impl VoiceEventHandler for TrackEndNotifier {
                     }
                     songbird::tracks::PlayMode::End => {
                         println!("stopped track_state: {track_state:?}");
-                        let () = guild.handle_track_end().await;
+                        guild.handle_track_end();
                     }
                     _ => {}
                 }
@@ -49,6 +62,44 @@ impl VoiceEventHandler for TrackEndNotifier {
     }
 }
 
+/// Forwards problems with the voice connection to the guild's worker.
+struct VoiceConnectionNotifier {
+    guild_id: GuildId,
+}
+
+#[async_trait]
+impl VoiceEventHandler for VoiceConnectionNotifier {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        match ctx {
+            EventContext::DriverDisconnect(data) => {
+                println!(
+                    "voice driver disconnected: {:?} {:?}",
+                    data.kind, data.reason
+                );
+                let guild = GUILD_STATES.get_guild_state(self.guild_id).await;
+                guild.handle_driver_disconnect(data.reason.map(|reason| format!("{reason:?}")));
+            }
+            EventContext::DriverReconnect(_) => {
+                println!("voice driver reconnected");
+                let guild = GUILD_STATES.get_guild_state(self.guild_id).await;
+                guild.handle_driver_reconnect();
+            }
+            _ => {}
+        }
+
+        None
+    }
+}
+
+/// Why the playback has been paused; decides whether it may be resumed automatically.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PauseReason {
+    /// somebody used the `pause` command; only a user may resume
+    User,
+    /// everybody left the voice channel
+    NoListeners,
+}
+
 #[derive(Clone)]
 pub struct QueueHandle(Arc<Mutex<Queue>>);
 
@@ -63,7 +114,27 @@ pub struct Queue {
     current_track_handle: Option<TrackHandle>,
     voice_connection: Option<Arc<Mutex<Call>>>,
     is_active: bool,
+    /// set while the current track is paused
+    pause_reason: Option<PauseReason>,
+    /// playback position of the current track when the voice connection has been lost
+    saved_position: Option<Duration>,
+    /// set while the bot is connected to a stage channel
+    stage: Option<Stage>,
+    panel: NowPlayingPanel,
+    /// repeat the current track instead of advancing to the next one
+    looping: bool,
     quota: Option<usize>,
+    history: QueueHistory,
+    /// tracks which have been removed but might come back by undoing the removal
+    removed_tracks: HashMap<TrackId, EnqueuedTrack>,
+    next_track_id: TrackId,
+    play_history: PlayHistory,
+    /// number of users in the bot's voice channel
+    listeners: usize,
+    /// user on whose behalf the current command is being executed
+    acting_user: Option<UserId>,
+    /// command which is being executed; all edits it makes are undone at once
+    acting_command: Option<CommandId>,
 }
 
 impl Queue {
@@ -77,7 +148,19 @@ impl Queue {
             current_track_handle: None,
             voice_connection: None,
             is_active: false,
+            pause_reason: None,
+            saved_position: None,
+            stage: None,
+            panel: NowPlayingPanel::default(),
+            looping: false,
             quota: None,
+            history: QueueHistory::default(),
+            removed_tracks: HashMap::new(),
+            next_track_id: 0,
+            play_history: PlayHistory::new(guild_id),
+            listeners: 0,
+            acting_user: None,
+            acting_command: None,
         }
     }
 
@@ -89,10 +172,83 @@ impl Queue {
         self.default_message_channel.set_channel(channel_id);
     }
 
-    pub fn connect(&mut self, voice_connection: Arc<Mutex<Call>>) {
+    pub async fn connect(&mut self, voice_connection: Arc<Mutex<Call>>) {
+        // songbird reuses the call when joining again; its handlers are still in place then
+        let is_new = !self
+            .voice_connection
+            .as_ref()
+            .is_some_and(|current| Arc::ptr_eq(current, &voice_connection));
+        if is_new {
+            let mut call = voice_connection.lock().await;
+            for event in [CoreEvent::DriverDisconnect, CoreEvent::DriverReconnect] {
+                call.add_global_event(
+                    Event::Core(event),
+                    VoiceConnectionNotifier {
+                        guild_id: self.guild_id,
+                    },
+                );
+            }
+        }
         self.voice_connection = Some(voice_connection);
     }
 
+    /// Replaces the stage the bot is connected to; the previous one will be cleaned up.
+    pub async fn set_stage(&mut self, stage: Option<Stage>) {
+        if let Some(previous) = std::mem::replace(&mut self.stage, stage) {
+            previous.clean_up().await;
+        }
+    }
+
+    pub fn stage(&self) -> Option<&Stage> {
+        self.stage.as_ref()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.voice_connection.is_some()
+    }
+
+    /// Whether the current track is audible right now; i.e. neither paused nor stopped.
+    pub async fn is_playing(&self) -> bool {
+        match &self.current_track_handle {
+            Some(handle) => handle
+                .get_info()
+                .await
+                .is_ok_and(|state| state.playing == PlayMode::Play),
+            None => false,
+        }
+    }
+
+    /// Remembers the playback position so it can be restored once the voice connection is back.
+    pub async fn save_position(&mut self) {
+        if let Some(handle) = &self.current_track_handle {
+            if let Ok(state) = handle.get_info().await {
+                self.saved_position = Some(state.position);
+            }
+        }
+    }
+
+    /// Replays the current track from the saved position if the voice driver lost it.
+    pub async fn restore_playback(&mut self) -> CommandResult {
+        let position = self.saved_position.take();
+        let is_lost = match &self.current_track_handle {
+            Some(handle) => handle.get_info().await.is_err(),
+            None => false,
+        };
+        if !is_lost {
+            return Ok(());
+        }
+
+        let pause_reason = self.pause_reason;
+        self.play().await?;
+        if let (Some(position), Some(handle)) = (position, &self.current_track_handle) {
+            handle.seek_time(position)?;
+        }
+        if let Some(reason) = pause_reason {
+            self.pause(reason)?;
+        }
+        Ok(())
+    }
+
     pub fn disconnect(&mut self) {
         let _ = self.stop();
         self.voice_connection = None;
@@ -136,8 +292,18 @@ impl Queue {
     pub async fn append(&mut self, user_track: Box<dyn Track>) {
         //let user_id = user_track.adding_user();
 
-        self.tracks.push(EnqueuedTrack { track: user_track });
+        let before = self.snapshot();
+        let user_name = user_track.adding_user().name.clone();
+        let track = self.enqueued(user_track);
+        self.tracks.push(track);
         self.try_enqueue_deferred();
+        self.record(
+            QueueEditKind::Enqueue {
+                user_name,
+                count: 1,
+            },
+            before,
+        );
 
         // // find proper insert location
         // let user_track_count = self.tracks[self.current_track_index..self.deferred_track_index].iter().filter(|track| track.track.adding_user() == user_id).count();
@@ -157,11 +323,346 @@ impl Queue {
     }
 
     pub async fn handle_track_end(&mut self) {
-        let _ = self.next().await;
+        self.play_history.finish(PlayOutcome::Completed);
+        if self.looping {
+            let _ = self.play().await;
+        } else {
+            let _ = self.go_to(TrackIndex::Current(1)).await;
+        }
+    }
+
+    /// Switches repeating the current track on or off.
+    pub async fn toggle_loop(&mut self, out: &MessageChannel) -> CommandResult {
+        self.looping = !self.looping;
+        if self.looping {
+            out.print_status(
+                "Looping the current track. Use `loop` again to continue with the queue.",
+            )
+            .await;
+        } else {
+            out.print_status("No longer looping the current track.")
+                .await;
+        }
+        Ok(())
+    }
+
+    /// Inserts tracks at the given position ahead of the tracks which are held back by the quota.
+    ///
+    /// Only DJs may place tracks ahead of upcoming tracks of other users. Tracks exceeding the
+    /// user's quota are held back as if they had been appended; DJs aren't limited by the quota.
+    ///
+    /// Positions relative to the current track which are beyond the end of the queue (e.g. for
+    /// `+next` while the queue is empty or has finished) mean the end of the queue.
+    pub async fn insert(
+        &mut self,
+        out: &MessageChannel,
+        tracks: Vec<Box<dyn Track>>,
+        index: TrackIndex,
+        is_dj: bool,
+    ) -> CommandResult {
+        self.try_enqueue_deferred();
+        let before = self.snapshot();
+
+        let mut insert_index = match index.resolve(self.current_track_index, self.tracks.len()) {
+            IndexResolve::Ok(index) | IndexResolve::End(index) => index,
+            IndexResolve::TooSmall(_) => 0,
+            IndexResolve::TooBig(_) if matches!(index, TrackIndex::Current(_)) => self.tracks.len(),
+            IndexResolve::TooBig(index) => {
+                return Err(CommandError::Execution(format!(
+                    "Destination slot #{} doesn't exist",
+                    index + 1
+                )));
+            }
+        };
+        let first_upcoming = (self.current_track_index + 1).min(self.tracks.len());
+        if insert_index < first_upcoming {
+            return Err(CommandError::Execution(format!(
+                "Tracks can only be inserted after the current track #{}.",
+                self.current_track_index + 1
+            )));
+        }
+        // tracks held back by the quota must not overtake the inserted ones
+        let is_clamped = insert_index > self.deferred_track_index;
+        insert_index = insert_index.min(self.deferred_track_index);
+        let first_inserted = insert_index;
+        if let Some(user_id) = tracks.first().map(|track| track.adding_user().id) {
+            let overtakes_others = self.tracks[insert_index..self.deferred_track_index]
+                .iter()
+                .any(|track| track.track.adding_user().id != user_id);
+            if overtakes_others && !is_dj {
+                return Err(CommandError::Execution(
+                    "Only DJs are allowed to place tracks ahead of the tracks of other users."
+                        .to_owned(),
+                ));
+            }
+        }
+
+        let mut count = 0;
+        let mut held_back = 0;
+        for track in tracks {
+            let user_id = track.adding_user().id;
+            let within_quota = self.quota.is_none_or(|quota| {
+                self.tracks[self.current_track_index..self.deferred_track_index]
+                    .iter()
+                    .filter(|track| track.track.adding_user().id == user_id)
+                    .count()
+                    < quota
+            });
+            let track = self.enqueued(track);
+            if is_dj || within_quota {
+                self.tracks.insert(insert_index, track);
+                insert_index += 1;
+                self.deferred_track_index += 1;
+                count += 1;
+            } else {
+                self.tracks.push(track);
+                held_back += 1;
+            }
+        }
+
+        if count > 0 {
+            let moved = if is_clamped {
+                " ahead of the tracks held back by the quota"
+            } else {
+                ""
+            };
+            out.print_status(format!(
+                "Inserted {count} track(s) at #{}{moved}.",
+                first_inserted + 1
+            ))
+            .await;
+        }
+        if held_back > 0 {
+            out.print_status(format!(
+                "Held back {held_back} track(s) which exceed your quota."
+            ))
+            .await;
+        }
+
+        self.try_enqueue_deferred();
+        self.record(
+            QueueEditKind::Insert {
+                count: count + held_back,
+            },
+            before,
+        );
+
+        // auto-start playback
+        if self.current_track_handle.is_none() && self.is_active {
+            let _ = self.play().await;
+        }
+        Ok(())
+    }
+
+    /// Remembers who issued the command which is about to be executed.
+    pub fn set_acting_user(&mut self, user_id: Option<UserId>) {
+        self.acting_user = user_id;
+    }
+
+    /// Remembers which command is about to be executed; a command might be executed in several
+    /// steps (e.g. enqueueing track by track).
+    pub fn set_acting_command(&mut self, command_id: Option<CommandId>) {
+        self.acting_command = command_id;
+    }
+
+    pub fn set_listener_count(&mut self, listeners: usize) {
+        self.listeners = listeners;
+    }
+
+    /// Lists the tracks which have been played recently.
+    pub async fn history(&self, out: &MessageChannel, count: usize) -> CommandResult {
+        out.send_embed(self.play_history.render(count), CreateComponents::default())
+            .await;
+        Ok(())
+    }
+
+    /// Picks up the plays from before the bot has been started.
+    pub async fn load_play_history(&mut self) {
+        self.play_history.load().await;
+    }
+
+    /// Shows what has been played during the given period.
+    pub async fn stats(
+        &self,
+        out: &MessageChannel,
+        category: Option<StatsCategory>,
+        period: StatsPeriod,
+    ) -> CommandResult {
+        let (start, end) = period.bounds(play_history::unix_time_now());
+        let plays = self
+            .play_history
+            .read_period(start, end)
+            .await
+            .map_err(|err| CommandError::Execution(format!("Failed to read the plays: {err}")))?;
+        let embed = stats::render(&plays, category, period);
+        out.send_embed(embed, CreateComponents::default()).await;
+        Ok(())
+    }
+
+    /// The selected tracks in queue order; ready to be stored in a playlist.
+    pub fn playlist_entries(
+        &self,
+        track_selection: &TrackIndexSelection,
+    ) -> CommandResult<Vec<PlaylistEntry>> {
+        let mut indices =
+            Vec::from_iter(track_selection.collect(self.current_track_index, self.tracks.len()));
+        indices.sort_unstable();
+        let entries: Vec<PlaylistEntry> = indices
+            .into_iter()
+            .map(|index| {
+                let track = &self.tracks[index];
+                PlaylistEntry {
+                    url: track.track.track_page_url().to_owned(),
+                    comment: track.track.comment(),
+                    caption: Some(track.activity()),
+                    duration: track.track.duration(),
+                }
+            })
+            .collect();
+        if entries.is_empty() {
+            return Err(CommandError::Execution(
+                "None of the given tracks is in the queue.".to_owned(),
+            ));
+        }
+        Ok(entries)
+    }
+
+    /// Saves the selected tracks as one of the user's personal playlists.
+    pub async fn save_playlist(
+        &self,
+        out: &MessageChannel,
+        user_id: UserId,
+        name: String,
+        track_selection: &TrackIndexSelection,
+    ) -> CommandResult {
+        let entries = self.playlist_entries(track_selection)?;
+        let mut store = PlaylistStore::open_personal(user_id).await?;
+        let track_count = entries.len();
+        store.put(Playlist {
+            name: name.clone(),
+            owner: None,
+            entries,
+        });
+        store.save().await?;
+        out.print_status(format!(
+            "Saved {track_count} track(s) as your playlist `{name}`."
+        ))
+        .await;
+        Ok(())
+    }
+
+    /// Reverts the latest edit of the queue.
+    pub async fn undo(&mut self, out: &MessageChannel) -> CommandResult {
+        let edit = self
+            .history
+            .undo()
+            .ok_or_else(|| CommandError::Execution("There's nothing to be undone.".to_owned()))?;
+        out.print_status(format!("Undid {}.", edit.kind)).await;
+        self.restore(&edit, &edit.before).await
+    }
+
+    /// Makes the latest undone edit of the queue again.
+    pub async fn redo(&mut self, out: &MessageChannel) -> CommandResult {
+        let edit = self
+            .history
+            .redo()
+            .ok_or_else(|| CommandError::Execution("There's nothing to be redone.".to_owned()))?;
+        out.print_status(format!("Redid {}.", edit.kind)).await;
+        self.restore(&edit, &edit.after).await
+    }
+
+    fn enqueued(&mut self, track: Box<dyn Track>) -> EnqueuedTrack {
+        let id = self.next_track_id;
+        self.next_track_id += 1;
+        EnqueuedTrack { id, track }
+    }
+
+    fn snapshot(&self) -> QueueSnapshot {
+        QueueSnapshot {
+            track_ids: self.tracks.iter().map(|track| track.id).collect(),
+            current_track_index: self.current_track_index,
+            deferred_track_index: self.deferred_track_index,
+            quota: self.quota,
+        }
+    }
+
+    /// Remembers an edit of the queue; `before` is the state prior to the edit.
+    fn record(&mut self, kind: QueueEditKind, before: QueueSnapshot) {
+        let after = self.snapshot();
+        if before == after {
+            return;
+        }
+        self.history.record(QueueEdit {
+            kind,
+            command_id: self.acting_command,
+            before,
+            after,
+        });
+        self.forget_removed_tracks();
+    }
+
+    /// Drops removed tracks which can't come back anymore.
+    fn forget_removed_tracks(&mut self) {
+        let referenced = self.history.referenced_track_ids();
+        self.removed_tracks.retain(|id, _| referenced.contains(id));
+    }
+
+    /// Brings the queue into the state of the snapshot.
+    ///
+    /// The current track keeps playing unless the edit was about navigating the queue.
+    async fn restore(&mut self, edit: &QueueEdit, snapshot: &QueueSnapshot) -> CommandResult {
+        let current_track_id = self
+            .tracks
+            .get(self.current_track_index)
+            .map(|track| track.id);
+
+        let mut available: HashMap<TrackId, EnqueuedTrack> = self
+            .tracks
+            .drain(..)
+            .chain(self.removed_tracks.drain().map(|(_, track)| track))
+            .map(|track| (track.id, track))
+            .collect();
+        self.tracks = snapshot
+            .track_ids
+            .iter()
+            .filter_map(|id| available.remove(id))
+            .collect();
+        self.removed_tracks = available;
+        self.forget_removed_tracks();
+
+        let still_current =
+            current_track_id.and_then(|id| self.tracks.iter().position(|track| track.id == id));
+        self.current_track_index = match still_current {
+            Some(index) if !edit.is_navigation() => index,
+            _ => snapshot.current_track_index.min(self.tracks.len()),
+        };
+        self.deferred_track_index = snapshot.deferred_track_index.min(self.tracks.len());
+        self.quota = snapshot.quota;
+        self.try_enqueue_deferred();
+
+        let is_same_track = self
+            .tracks
+            .get(self.current_track_index)
+            .map(|track| track.id)
+            == current_track_id;
+        if is_same_track || !self.is_active {
+            Ok(())
+        } else if self.current_track_index < self.tracks.len() {
+            self.play().await
+        } else {
+            // nothing left to play; this just stops the previous track
+            let _ = self.play().await;
+            Ok(())
+        }
     }
 
     pub fn stop(&mut self) -> CommandResult {
         println!("queue::stop");
+        presence::reset(self.guild_id);
+        self.play_history.finish(PlayOutcome::Skipped {
+            by: self.acting_user,
+        });
+        self.pause_reason = None;
         if let Some(track) = self.current_track_handle.take() {
             track.stop()?;
         } else if !self.is_active {
@@ -173,9 +674,11 @@ impl Queue {
         Ok(())
     }
 
-    pub fn pause(&mut self) -> CommandResult {
+    pub fn pause(&mut self, reason: PauseReason) -> CommandResult {
         if let Some(track) = &self.current_track_handle {
             track.pause()?;
+            self.pause_reason = Some(reason);
+            self.play_history.pause();
         } else {
             return Err(CommandError::Execution(
                 "There's nothing to be paused.".to_owned(),
@@ -198,6 +701,8 @@ impl Queue {
     pub fn resume(&mut self) -> CommandResult {
         if let Some(track) = &self.current_track_handle {
             track.play()?;
+            self.pause_reason = None;
+            self.play_history.resume();
         } else {
             return Err(CommandError::Execution(
                 "There's nothing to be resumed.".to_owned(),
@@ -206,6 +711,13 @@ impl Queue {
         Ok(())
     }
 
+    /// Resumes the playback only if it has been paused for the given reason.
+    ///
+    /// Returns whether the playback has been resumed.
+    pub fn resume_paused_by(&mut self, reason: PauseReason) -> bool {
+        self.pause_reason == Some(reason) && self.resume().is_ok()
+    }
+
     pub async fn play(&mut self) -> CommandResult {
         println!("queue::play");
 
@@ -219,27 +731,76 @@ impl Queue {
         if let Some(track) = self.current_track_handle.take() {
             println!("queue::play > stop previous {track:?}");
             let _ = track.stop();
+            self.play_history.finish(PlayOutcome::Skipped {
+                by: self.acting_user,
+            });
         }
+        self.pause_reason = None;
 
-        let track = self.tracks.get(self.current_track_index)
-                .ok_or_else(|| CommandError::Execution("There's no track in the queue to be played. Use the `enqueue` command to add some tracks.".to_owned()))?;
+        let Some(track) = self.tracks.get(self.current_track_index) else {
+            presence::reset(self.guild_id);
+            return Err(CommandError::Execution("There's no track in the queue to be played. Use the `enqueue` command to add some tracks.".to_owned()));
+        };
 
-        self.current_track_handle = Some(
-            track
-                .play(
-                    voice_connection,
-                    &self.default_message_channel,
-                    self.guild_id,
-                )
-                .await?,
-        );
+        self.current_track_handle = Some(track.play(voice_connection, self.guild_id).await?);
+        self.play_history
+            .start(track.track.as_ref(), self.listeners);
         println!(
             "queue::play > current_track_handle {:?}",
             self.current_track_handle
         );
+        self.announce().await;
         Ok(())
     }
 
+    /// The message showing the now-playing panel (if any).
+    pub fn panel_message_id(&self) -> Option<MessageId> {
+        self.panel.message_id()
+    }
+
+    /// Shows the current track in the now-playing panel and as the topic of the stage (if any).
+    async fn announce(&mut self) {
+        let content = self.panel_content().await;
+        self.panel
+            .show(&self.default_message_channel, &content)
+            .await;
+
+        if let (Some(stage), Some(track)) = (&self.stage, self.tracks.get(self.current_track_index))
+        {
+            stage.set_topic(&track.topic()).await;
+        }
+    }
+
+    /// Updates the now-playing panel (if any) to the current state of the playback.
+    pub async fn refresh_panel(&mut self) {
+        let content = self.panel_content().await;
+        self.panel.refresh(&content).await;
+    }
+
+    async fn panel_content(&self) -> PanelContent {
+        let state = match &self.current_track_handle {
+            Some(handle) if self.is_active => handle.get_info().await.ok(),
+            _ => None,
+        };
+        let playback = match state.as_ref().map(|state| state.playing) {
+            Some(PlayMode::Play) => Playback::Playing,
+            Some(PlayMode::Pause) => Playback::Paused,
+            _ => Playback::Stopped,
+        };
+        let track_at = |index: usize| self.tracks.get(index).map(|track| &*track.track);
+
+        PanelView {
+            playback,
+            track: track_at(self.current_track_index),
+            position: state.map(|state| state.position),
+            track_number: self.current_track_index + 1,
+            track_count: self.tracks.len(),
+            next_track: track_at(self.current_track_index + 1),
+            looping: self.looping,
+        }
+        .render()
+    }
+
     pub async fn deafen(&mut self) -> CommandResult {
         let voice_connection = self
             .voice_connection
@@ -276,35 +837,49 @@ impl Queue {
         tracks.sort_unstable();
         println!("printing tracks: {tracks:?}");
 
-        let mut message = "Current queue:".to_owned();
-        for (index, track) in tracks.iter().map(|&index| (index, &self.tracks[index])) {
-            if index == self.current_track_index {
-                message.push_str(&format!("\n`▶{:>4}:` {} `◀`", index + 1, track.caption()));
-            } else if index < self.deferred_track_index {
-                message.push_str(&format!("\n` {:>4}:` {}", index + 1, track.caption()));
-            } else {
-                message.push_str(&format!("\n`?{:>4}:` {}", index + 1, track.caption()));
-            }
-        }
+        self.queue_page(&tracks).send(out).await;
+        Ok(())
+    }
 
-        if let Some(&last_index) = tracks.last() {
-            if last_index + 1 < self.tracks.len() {
-                message.push_str(&format!(
-                    "\n`  ...:` (and {} more track(s))",
-                    self.tracks.len() - last_index - 1
-                ));
-            } else if self.current_track_index == self.tracks.len() {
-                message.push_str("\n`▶ END:` (you've reached the end of the queue) `◀`");
-            } else {
-                message.push_str("\n`  END:` (you've reached the end of the queue)");
-            }
-        }
+    /// Renders the page of the queue starting at the given track; used by the page buttons.
+    pub fn queue_page_at(&self, start: usize) -> QueuePage {
+        let start = start.min(self.tracks.len().saturating_sub(1));
+        let end = (start + QUEUE_PAGE_SIZE).min(self.tracks.len());
+        self.queue_page(&(start..end).collect::<Vec<_>>())
+    }
 
-        out.print(message).await;
-        Ok(())
+    fn queue_page(&self, track_indices: &[usize]) -> QueuePage {
+        QueuePageView {
+            entries: track_indices
+                .iter()
+                .take(QUEUE_PAGE_SIZE)
+                .map(|&index| QueueEntry {
+                    index,
+                    track: &*self.tracks[index].track,
+                    is_deferred: index >= self.deferred_track_index,
+                })
+                .collect(),
+            selected_count: track_indices.len(),
+            current_track_index: self.current_track_index,
+            track_count: self.tracks.len(),
+        }
+        .render()
     }
 
+    /// Jumps to another track on behalf of a user; can be undone.
     pub async fn goto(&mut self, track_index: TrackIndex) -> CommandResult {
+        let before = self.snapshot();
+        let result = self.go_to(track_index).await;
+        self.record(
+            QueueEditKind::Goto {
+                track_number: self.current_track_index + 1,
+            },
+            before,
+        );
+        result
+    }
+
+    async fn go_to(&mut self, track_index: TrackIndex) -> CommandResult {
         match track_index.resolve(self.current_track_index, self.tracks.len()) {
             IndexResolve::Ok(index) | IndexResolve::End(index) => {
                 self.current_track_index = index;
@@ -352,9 +927,11 @@ impl Queue {
         tracks.sort_by(|a, b| b.cmp(a));
         println!("removing tracks: {tracks:?}");
 
+        let before = self.snapshot();
         let mut killed_current = false;
         for track in tracks {
-            self.tracks.remove(track);
+            let removed = self.tracks.remove(track);
+            self.removed_tracks.insert(removed.id, removed);
             match track.cmp(&self.current_track_index) {
                 std::cmp::Ordering::Less => self.current_track_index -= 1,
                 std::cmp::Ordering::Equal => killed_current = true,
@@ -365,8 +942,9 @@ impl Queue {
             }
         }
         self.try_enqueue_deferred();
+        self.record(QueueEditKind::Remove { count: track_count }, before);
 
-        out.print(format!("Removed {track_count} track(s) from the queue."))
+        out.print_status(format!("Removed {track_count} track(s) from the queue."))
             .await;
 
         if killed_current && self.is_active {
@@ -377,14 +955,11 @@ impl Queue {
         }
     }
 
-    pub async fn now(&self, out: &MessageChannel) -> CommandResult {
-        if let (true, Some(track), Some(handle)) = (
-            self.is_active,
-            self.tracks.get(self.current_track_index),
-            &self.current_track_handle,
-        ) {
-            track.announce(out).await;
-            track.announce_position(out, handle).await;
+    /// Moves the now-playing panel to the bottom of the given channel.
+    pub async fn now(&mut self, out: &MessageChannel) -> CommandResult {
+        if self.tracks.get(self.current_track_index).is_some() {
+            let content = self.panel_content().await;
+            self.panel.repost(out, &content).await;
             Ok(())
         } else {
             out.print("There's no current track. Use the `enqueue` command to add some tracks.")
@@ -393,11 +968,36 @@ impl Queue {
         }
     }
 
+    pub async fn info(&self, out: &MessageChannel, track_index: TrackIndex) -> CommandResult {
+        let index = match track_index.resolve(self.current_track_index, self.tracks.len()) {
+            IndexResolve::Ok(index) => index,
+            IndexResolve::TooSmall(_) | IndexResolve::TooBig(_) | IndexResolve::End(_) => {
+                return Err(CommandError::Execution(format!(
+                    "Track #{} doesn't exist.",
+                    track_index.resolve_raw(self.current_track_index, self.tracks.len()) + 1
+                )));
+            }
+        };
+
+        let track = &self.tracks[index];
+        out.print(format!(
+            "Track #{}: {}\n{}",
+            index + 1,
+            track.caption(),
+            track.track.track_page_url()
+        ))
+        .await;
+        track.announce_details(out).await;
+        track.announce_latest_comments(out);
+        Ok(())
+    }
+
     pub async fn reverse(&mut self, track_selection: &TrackIndexSelection) -> CommandResult {
         let set = track_selection.collect(self.current_track_index, self.tracks.len());
 
         let mut tracks = Vec::from_iter(set);
         tracks.sort_unstable();
+        let before = self.snapshot();
         let mut killed_current = false;
         for (i, j) in (0..tracks.len() / 2).map(|i| (tracks[i], tracks[tracks.len() - 1 - i])) {
             killed_current |= self.current_track_index == i;
@@ -406,6 +1006,12 @@ impl Queue {
         }
 
         self.try_enqueue_deferred();
+        self.record(
+            QueueEditKind::Reverse {
+                count: tracks.len(),
+            },
+            before,
+        );
 
         if killed_current && self.is_active {
             self.play().await
@@ -416,8 +1022,10 @@ impl Queue {
 
     #[allow(clippy::unnecessary_wraps)] // for symmetry with other commands
     pub fn set_quota(&mut self, quota: Option<usize>) -> CommandResult {
+        let before = self.snapshot();
         self.quota = quota;
         self.try_enqueue_deferred();
+        self.record(QueueEditKind::Quota { quota }, before);
         Ok(())
     }
 
@@ -464,6 +1072,7 @@ impl Queue {
         tracks_to_move.sort_by(|a, b| b.cmp(a));
         println!("moving tracks: {tracks_to_move:?}");
 
+        let before = self.snapshot();
         let mut moved_tracks = Vec::new();
 
         let mut killed_current = false;
@@ -486,9 +1095,11 @@ impl Queue {
         self.tracks.extend(moved_tracks);
         self.tracks.extend(tail);
 
-        out.print(format!("Moved {track_count} track(s).")).await;
+        out.print_status(format!("Moved {track_count} track(s)."))
+            .await;
 
         self.try_enqueue_deferred();
+        self.record(QueueEditKind::Move { count: track_count }, before);
 
         if killed_current && self.is_active {
             self.play().await
@@ -590,6 +1201,8 @@ impl Queue {
 }
 
 struct EnqueuedTrack {
+    /// stays the same while the track is being moved around; even after it has been removed
+    id: TrackId,
     track: Box<dyn Track>,
 }
 
@@ -598,62 +1211,93 @@ impl EnqueuedTrack {
         self.track.caption()
     }
 
-    async fn announce(&self, out: &MessageChannel) {
-        let message = format!(
-            "Now playing: {}\n{}",
-            self.caption(),
-            self.track.track_page_url()
-        );
-        out.print(message).await;
-    }
-
-    async fn announce_position(&self, out: &MessageChannel, track: &TrackHandle) {
-        const WIDTH: usize = 40;
-
-        if let Some(duration) = self.track.duration() {
-            let duration = duration.as_secs();
-
-            if let Ok(state) = track.get_info().await {
-                let position = state.position.as_secs().min(duration);
-                let (position_minutes, position_seconds) = (position / 60, position % 60);
-                let remaining = duration - position;
-                let (remaining_minutes, remaining_seconds) = (remaining / 60, remaining % 60);
-
-                #[allow(
-                    clippy::cast_possible_truncation,
-                    clippy::cast_sign_loss,
-                    clippy::cast_precision_loss
-                )]
-                let shift = (WIDTH as f32 * position as f32 / duration as f32).round() as usize;
-
-                let mut message = "```".to_owned();
-                message.push_str("╔══════╕                                       ╒══════╗");
-                message.push_str("\n║");
-                (0..shift).for_each(|_| message.push('•'));
-                message.push_str(&format!("[{position_minutes:0>2}:{position_seconds:0>2}│{remaining_minutes:0>2}:{remaining_seconds:0>2}]"));
-                (shift..WIDTH).for_each(|_| message.push('•'));
-                message.push('║');
-                message.push_str("\n╚══════╛                                       ╘══════╝");
-                message.push_str("```");
-                out.print(message).await;
-            } else {
-                out.print("Could not determine the track's current state.")
-                    .await;
-            }
-        } else {
-            out.print("Could not determine the track's duration.").await;
+    /// Topic of the stage while this track is playing.
+    fn topic(&self) -> String {
+        format!("🎵 {}", self.activity())
+    }
+
+    /// Shown as the bot's activity while this track is playing; e.g. `artist – title`.
+    fn activity(&self) -> String {
+        format!(
+            "{} – {}",
+            self.track
+                .artist()
+                .unwrap_or_else(|| "(unknown artist)".to_owned()),
+            self.track
+                .title()
+                .unwrap_or_else(|| "(unknown title)".to_owned())
+        )
+    }
+
+    /// Prints whatever the track's source knows about the track beyond the caption.
+    async fn announce_details(&self, out: &MessageChannel) {
+        let mut details = Vec::new();
+        if let Some(artist_url) = self.track.artist_url() {
+            details.push(format!("Artist: <{artist_url}>"));
+        }
+        if let Some(remix_of) = self.track.remix_of() {
+            details.push(format!(
+                "Remix of: **{}** - **{}** <{}>",
+                remix_of.artist.as_deref().unwrap_or("(unknown artist)"),
+                remix_of.title.as_deref().unwrap_or("(unknown title)"),
+                remix_of.track_page_url
+            ));
+        }
+        if let Some(stats) = self.track.stats() {
+            details.push(stats.to_string());
+        }
+        if !details.is_empty() {
+            out.print(details.join("\n")).await;
         }
     }
 
+    /// Fetches the latest comments in the background, so the queue isn't blocked meanwhile.
+    fn announce_latest_comments(&self, out: &MessageChannel) {
+        const COMMENT_COUNT: usize = 3;
+
+        let Some(comments) = self.track.latest_comments(COMMENT_COUNT) else {
+            return;
+        };
+        let out = out.clone();
+        tokio::spawn(async move {
+            match comments.await {
+                Ok(comments) if comments.is_empty() => {
+                    out.print("Nobody has commented on this track yet.").await;
+                }
+                Ok(comments) => {
+                    let mut lines = vec!["Latest comments:".to_owned()];
+                    lines.extend(comments.iter().map(|comment| {
+                        format!(
+                            "> **{}**: {}",
+                            comment.author.as_deref().unwrap_or("(unknown user)"),
+                            comment.text.replace('\n', " ")
+                        )
+                    }));
+                    out.print(lines.join("\n")).await;
+                }
+                Err(err) => {
+                    eprintln!("failed to fetch comments: {err}");
+                    out.print(format!("Could not load the latest comments: {err}"))
+                        .await;
+                }
+            }
+        });
+    }
+
     pub async fn play(
         &self,
         voice_connection: &Mutex<Call>,
-        out: &MessageChannel,
         guild_id: GuildId,
     ) -> CommandResult<TrackHandle> {
         println!("EnqueuedTrack::play > current_track_handle");
         let user_track = &self.track;
-        let url = user_track.playback_url().to_owned();
+        let url = user_track.playback_url().map_err(|why| {
+            CommandError::Discord(format!(
+                "Failed to play track: {}\n{}",
+                self.track.caption(),
+                why
+            ))
+        })?;
         println!("playing url: {url}");
 
         // Here, we use lazy restartable sources to make sure that we don't pay
@@ -673,11 +1317,243 @@ impl EnqueuedTrack {
         let track_handle = voice_session.play_only_source(source.into());
 
         track_handle.add_event(Event::Track(TrackEvent::End), TrackEndNotifier { guild_id })?;
+        presence::set_playing(guild_id, self.activity());
 
         println!("EnqueuedTrack::play > current_track_handle");
 
-        self.announce(out).await;
-
         Ok(track_handle)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serenity::model::prelude::User;
+
+    use super::*;
+
+    /// A track which can't be played; good enough to rearrange the queue.
+    struct StubTrack {
+        title: String,
+        url: String,
+        user: User,
+    }
+
+    #[serenity::async_trait]
+    impl Track for StubTrack {
+        fn track_page_url(&self) -> &str {
+            &self.url
+        }
+        fn playback_url(&self) -> Result<String, String> {
+            Ok(self.url.clone())
+        }
+        fn duration(&self) -> Option<Duration> {
+            None
+        }
+        fn title(&self) -> Option<String> {
+            Some(self.title.clone())
+        }
+        fn cover_url(&self) -> Option<String> {
+            None
+        }
+        fn bpm(&self) -> Option<f64> {
+            None
+        }
+        fn genre(&self) -> Option<String> {
+            None
+        }
+        fn artist(&self) -> Option<String> {
+            None
+        }
+        fn created(&self) -> Option<String> {
+            None
+        }
+        fn comment(&self) -> Option<String> {
+            None
+        }
+        fn adding_user(&self) -> &User {
+            &self.user
+        }
+    }
+
+    fn stub_track(title: &str) -> Box<dyn Track> {
+        stub_track_by(title, User::default().id)
+    }
+
+    fn stub_track_by(title: &str, user_id: UserId) -> Box<dyn Track> {
+        let mut user = User::default();
+        user.id = user_id;
+        Box::new(StubTrack {
+            title: title.to_owned(),
+            url: format!("https://example.com/{title}"),
+            user,
+        })
+    }
+
+    async fn queue_of(titles: &[&str]) -> Queue {
+        let mut queue = Queue::new(GuildId(1), MessageChannel::default());
+        for title in titles {
+            queue.append(stub_track(title)).await;
+        }
+        queue
+    }
+
+    fn titles(queue: &Queue) -> Vec<String> {
+        queue
+            .tracks
+            .iter()
+            .map(|track| track.track.title().unwrap_or_default())
+            .collect()
+    }
+
+    fn current_title(queue: &Queue) -> Option<String> {
+        queue
+            .tracks
+            .get(queue.current_track_index)
+            .and_then(|track| track.track.title())
+    }
+
+    fn selection(s: &str) -> TrackIndexSelection {
+        TrackIndexSelection::parse_str(s).expect("invalid track selection")
+    }
+
+    #[tokio::test]
+    async fn removed_tracks_come_back_on_undo() {
+        let mut queue = queue_of(&["a", "b", "c"]).await;
+        let out = MessageChannel::default();
+
+        queue.remove(&out, &selection("1, 3")).await.unwrap();
+        assert_eq!(titles(&queue), ["b"]);
+
+        queue.undo(&out).await.unwrap();
+        assert_eq!(titles(&queue), ["a", "b", "c"]);
+
+        queue.redo(&out).await.unwrap();
+        assert_eq!(titles(&queue), ["b"]);
+    }
+
+    #[tokio::test]
+    async fn undoing_an_enqueue_removes_all_tracks_of_the_command_at_once() {
+        let mut queue = queue_of(&[]).await;
+        let out = MessageChannel::default();
+
+        queue.set_acting_command(Some(1));
+        queue.append(stub_track("a")).await;
+        queue.append(stub_track("b")).await;
+        // a separate command of the same user
+        queue.set_acting_command(Some(2));
+        queue.append(stub_track("c")).await;
+        queue.set_acting_command(None);
+
+        queue.undo(&out).await.unwrap();
+        assert_eq!(titles(&queue), ["a", "b"]);
+        queue.undo(&out).await.unwrap();
+        assert!(titles(&queue).is_empty());
+        assert!(queue.undo(&out).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn undoing_an_edit_keeps_the_current_track() {
+        let mut queue = queue_of(&["a", "b", "c", "d"]).await;
+        let out = MessageChannel::default();
+
+        queue.remove(&out, &selection("4")).await.unwrap();
+        // playback moved on since the removal
+        queue.handle_track_end().await;
+        assert_eq!(current_title(&queue).as_deref(), Some("b"));
+
+        queue.undo(&out).await.unwrap();
+        assert_eq!(titles(&queue), ["a", "b", "c", "d"]);
+        assert_eq!(current_title(&queue).as_deref(), Some("b"));
+    }
+
+    #[tokio::test]
+    async fn undoing_a_jump_goes_back() {
+        let mut queue = queue_of(&["a", "b", "c"]).await;
+        let out = MessageChannel::default();
+
+        queue.goto(TrackIndex::Start(2)).await.unwrap();
+        assert_eq!(current_title(&queue).as_deref(), Some("c"));
+
+        queue.undo(&out).await.unwrap();
+        assert_eq!(current_title(&queue).as_deref(), Some("a"));
+
+        queue.redo(&out).await.unwrap();
+        assert_eq!(current_title(&queue).as_deref(), Some("c"));
+    }
+
+    #[tokio::test]
+    async fn tracks_are_inserted_after_the_current_one() {
+        let mut queue = queue_of(&["a", "b", "c"]).await;
+        let out = MessageChannel::default();
+
+        let tracks = vec![stub_track("x"), stub_track("y")];
+        queue
+            .insert(&out, tracks, TrackIndex::Current(1), false)
+            .await
+            .unwrap();
+        assert_eq!(titles(&queue), ["a", "x", "y", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn only_djs_may_insert_ahead_of_other_users() {
+        let mut queue = queue_of(&["a"]).await;
+        let out = MessageChannel::default();
+        queue.append(stub_track_by("b", UserId(2))).await;
+        queue.append(stub_track_by("c", UserId(3))).await;
+
+        let tracks = vec![stub_track_by("x", UserId(3))];
+        assert!(queue
+            .insert(&out, tracks, TrackIndex::Current(1), false)
+            .await
+            .is_err());
+        assert_eq!(titles(&queue), ["a", "b", "c"]);
+
+        // ahead of the own tracks only
+        let tracks = vec![stub_track_by("y", UserId(3))];
+        queue
+            .insert(&out, tracks, TrackIndex::Start(2), false)
+            .await
+            .unwrap();
+        assert_eq!(titles(&queue), ["a", "b", "y", "c"]);
+
+        let tracks = vec![stub_track_by("z", UserId(3))];
+        queue
+            .insert(&out, tracks, TrackIndex::Current(1), true)
+            .await
+            .unwrap();
+        assert_eq!(titles(&queue), ["a", "z", "b", "y", "c"]);
+    }
+
+    #[tokio::test]
+    async fn tracks_can_be_inserted_next_into_an_empty_queue() {
+        let mut queue = queue_of(&[]).await;
+        let out = MessageChannel::default();
+
+        let tracks = vec![stub_track("x")];
+        queue
+            .insert(&out, tracks, TrackIndex::Current(1), false)
+            .await
+            .unwrap();
+        assert_eq!(titles(&queue), ["x"]);
+
+        let tracks = vec![stub_track("y")];
+        assert!(queue
+            .insert(&out, tracks, TrackIndex::Start(5), false)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn tracks_can_be_inserted_next_into_a_finished_queue() {
+        let mut queue = queue_of(&["a"]).await;
+        let out = MessageChannel::default();
+        queue.handle_track_end().await;
+
+        let tracks = vec![stub_track("x")];
+        queue
+            .insert(&out, tracks, TrackIndex::Current(1), false)
+            .await
+            .unwrap();
+        assert_eq!(titles(&queue), ["a", "x"]);
+    }
+}
@@ -0,0 +1,288 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt,
+};
+
+/// Number of queue edits which can be undone.
+const MAX_UNDO_STEPS: usize = 50;
+
+/// Identifies an enqueued track, even after it has been removed from the queue.
+pub type TrackId = u64;
+
+/// Identifies a single command of a user; a command may edit the queue several times (e.g. by
+/// enqueueing one track after another).
+pub type CommandId = u64;
+
+/// Order of the queue at some point in time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueueSnapshot {
+    pub track_ids: Vec<TrackId>,
+    pub current_track_index: usize,
+    pub deferred_track_index: usize,
+    pub quota: Option<usize>,
+}
+
+/// What kind of change has been made to the queue; shown when undoing or redoing it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum QueueEditKind {
+    /// all tracks enqueued by a single command are undone at once
+    Enqueue {
+        user_name: String,
+        count: usize,
+    },
+    Insert {
+        count: usize,
+    },
+    Remove {
+        count: usize,
+    },
+    Move {
+        count: usize,
+    },
+    Reverse {
+        count: usize,
+    },
+    /// 1-based number of the track which became the current one
+    Goto {
+        track_number: usize,
+    },
+    Quota {
+        quota: Option<usize>,
+    },
+}
+
+impl fmt::Display for QueueEditKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueueEditKind::Enqueue { user_name, count } => {
+                write!(f, "enqueueing {count} track(s) by `@{user_name}`")
+            }
+            QueueEditKind::Insert { count } => write!(f, "inserting {count} track(s)"),
+            QueueEditKind::Remove { count } => write!(f, "removing {count} track(s)"),
+            QueueEditKind::Move { count } => write!(f, "moving {count} track(s)"),
+            QueueEditKind::Reverse { count } => write!(f, "reversing {count} track(s)"),
+            QueueEditKind::Goto { track_number } => write!(f, "going to track #{track_number}"),
+            QueueEditKind::Quota { quota: None } => write!(f, "switching the quota off"),
+            QueueEditKind::Quota { quota: Some(quota) } => {
+                write!(f, "setting the quota to {quota}")
+            }
+        }
+    }
+}
+
+/// A change of the queue which can be undone and redone.
+#[derive(Clone, Debug)]
+pub struct QueueEdit {
+    pub kind: QueueEditKind,
+    /// command which made the edit; `None` if the bot made it on its own
+    pub command_id: Option<CommandId>,
+    pub before: QueueSnapshot,
+    pub after: QueueSnapshot,
+}
+
+impl QueueEdit {
+    /// Whether undoing or redoing the edit also changes the current track; other edits keep
+    /// playing whatever is playing right now.
+    pub fn is_navigation(&self) -> bool {
+        matches!(self.kind, QueueEditKind::Goto { .. })
+    }
+}
+
+/// Recent edits of a guild's queue; the oldest ones are forgotten.
+#[derive(Default)]
+pub struct QueueHistory {
+    undo: VecDeque<QueueEdit>,
+    redo: Vec<QueueEdit>,
+}
+
+impl QueueHistory {
+    /// Remembers an edit which has just been made; edits which have been undone can't be redone
+    /// anymore.
+    pub fn record(&mut self, edit: QueueEdit) {
+        self.redo.clear();
+
+        if let (Some(last), QueueEditKind::Enqueue { count: added, .. }) =
+            (self.undo.back_mut(), &edit.kind)
+        {
+            if let QueueEditKind::Enqueue { count, .. } = &mut last.kind {
+                if edit.command_id.is_some()
+                    && last.command_id == edit.command_id
+                    && last.after == edit.before
+                {
+                    *count += added;
+                    last.after = edit.after;
+                    return;
+                }
+            }
+        }
+
+        self.undo.push_back(edit);
+        if self.undo.len() > MAX_UNDO_STEPS {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Takes the latest edit to be undone; it can be redone afterwards.
+    pub fn undo(&mut self) -> Option<QueueEdit> {
+        let edit = self.undo.pop_back()?;
+        self.redo.push(edit.clone());
+        Some(edit)
+    }
+
+    /// Takes the latest undone edit to be made again.
+    pub fn redo(&mut self) -> Option<QueueEdit> {
+        let edit = self.redo.pop()?;
+        self.undo.push_back(edit.clone());
+        Some(edit)
+    }
+
+    /// Tracks which might come back into the queue by undoing or redoing an edit.
+    pub fn referenced_track_ids(&self) -> HashSet<TrackId> {
+        self.undo
+            .iter()
+            .chain(&self.redo)
+            .flat_map(|edit| edit.before.track_ids.iter().chain(&edit.after.track_ids))
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(track_ids: &[TrackId]) -> QueueSnapshot {
+        QueueSnapshot {
+            track_ids: track_ids.to_vec(),
+            current_track_index: 0,
+            deferred_track_index: track_ids.len(),
+            quota: None,
+        }
+    }
+
+    fn enqueue(
+        user_name: &str,
+        command_id: CommandId,
+        before: &[TrackId],
+        after: &[TrackId],
+    ) -> QueueEdit {
+        QueueEdit {
+            kind: QueueEditKind::Enqueue {
+                user_name: user_name.to_owned(),
+                count: after.len() - before.len(),
+            },
+            command_id: Some(command_id),
+            before: snapshot(before),
+            after: snapshot(after),
+        }
+    }
+
+    #[test]
+    fn enqueues_of_a_single_command_are_merged() {
+        let mut history = QueueHistory::default();
+        history.record(enqueue("alice", 1, &[], &[1]));
+        history.record(enqueue("alice", 1, &[1], &[1, 2]));
+        history.record(enqueue("alice", 1, &[1, 2], &[1, 2, 3]));
+
+        let edit = history.undo().expect("nothing to undo");
+        assert_eq!(
+            edit.kind,
+            QueueEditKind::Enqueue {
+                user_name: "alice".to_owned(),
+                count: 3
+            }
+        );
+        assert_eq!(edit.before, snapshot(&[]));
+        assert_eq!(edit.after, snapshot(&[1, 2, 3]));
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn separate_commands_of_the_same_user_are_kept_apart() {
+        let mut history = QueueHistory::default();
+        history.record(enqueue("alice", 1, &[], &[1]));
+        history.record(enqueue("alice", 2, &[1], &[1, 2]));
+
+        assert_eq!(
+            history.undo().expect("nothing to undo").before,
+            snapshot(&[1])
+        );
+        assert_eq!(
+            history.undo().expect("nothing to undo").before,
+            snapshot(&[])
+        );
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn enqueues_of_different_commands_are_kept_apart() {
+        let mut history = QueueHistory::default();
+        history.record(enqueue("alice", 1, &[], &[1]));
+        history.record(enqueue("bob", 2, &[1], &[1, 2]));
+        // the queue changed in between, so this one can't be merged with bob's enqueue either
+        history.record(enqueue("bob", 2, &[2], &[2, 3]));
+
+        assert_eq!(
+            history.undo().expect("nothing to undo").before,
+            snapshot(&[2])
+        );
+        assert_eq!(
+            history.undo().expect("nothing to undo").before,
+            snapshot(&[1])
+        );
+        assert_eq!(
+            history.undo().expect("nothing to undo").before,
+            snapshot(&[])
+        );
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn only_the_latest_edits_can_be_undone() {
+        let mut history = QueueHistory::default();
+        for id in 0..(MAX_UNDO_STEPS as TrackId + 10) {
+            history.record(QueueEdit {
+                kind: QueueEditKind::Remove { count: 1 },
+                command_id: Some(id),
+                before: snapshot(&[id]),
+                after: snapshot(&[]),
+            });
+        }
+
+        let mut undone = Vec::new();
+        while let Some(edit) = history.undo() {
+            undone.push(edit.before.track_ids[0]);
+        }
+        assert_eq!(undone.len(), MAX_UNDO_STEPS);
+        assert_eq!(undone.first(), Some(&(MAX_UNDO_STEPS as TrackId + 9)));
+        assert_eq!(undone.last(), Some(&10));
+    }
+
+    #[test]
+    fn undone_edits_can_be_redone_until_something_else_changes() {
+        let mut history = QueueHistory::default();
+        history.record(enqueue("alice", 1, &[], &[1]));
+        history.record(enqueue("bob", 2, &[1], &[1, 2]));
+
+        assert!(history.undo().is_some());
+        let edit = history.redo().expect("nothing to redo");
+        assert_eq!(edit.after, snapshot(&[1, 2]));
+        assert!(history.redo().is_none());
+
+        assert!(history.undo().is_some());
+        history.record(enqueue("carol", 3, &[1], &[1, 3]));
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn tracks_of_undone_and_redoable_edits_are_referenced() {
+        let mut history = QueueHistory::default();
+        history.record(enqueue("alice", 1, &[], &[1]));
+        history.record(enqueue("bob", 2, &[1], &[1, 2]));
+        history.undo();
+
+        let mut referenced: Vec<TrackId> = history.referenced_track_ids().into_iter().collect();
+        referenced.sort_unstable();
+        assert_eq!(referenced, vec![1, 2]);
+    }
+}
@@ -0,0 +1,208 @@
+use serenity::{
+    builder::{CreateComponents, CreateEmbed},
+    http::Http,
+    model::{
+        application::{
+            component::ButtonStyle,
+            interaction::{
+                message_component::MessageComponentInteraction, InteractionResponseType,
+            },
+        },
+        channel::ReactionType,
+    },
+};
+
+use crate::{
+    command::{CommandError, CommandResult},
+    message::MessageChannel,
+    track::Track,
+};
+
+/// Maximum number of tracks shown at once; keeps the embed below Discord's size limit.
+pub const QUEUE_PAGE_SIZE: usize = 15;
+
+/// Component ids of the page buttons carry the index of the first track to show.
+const PAGE_BUTTON_PREFIX: &str = "queue-page-";
+
+/// Artists and titles are cut down to keep the whole embed below Discord's size limit.
+const MAX_NAME_LENGTH: usize = 60;
+
+const COLOR_QUEUE: u32 = 0x0058_65f2;
+
+/// Returns the index of the first track to show if the component is one of the page buttons.
+pub fn page_start_from_custom_id(custom_id: &str) -> Option<usize> {
+    custom_id.strip_prefix(PAGE_BUTTON_PREFIX)?.parse().ok()
+}
+
+pub struct QueueEntry<'a> {
+    pub index: usize,
+    pub track: &'a dyn Track,
+    /// held back until the user who added it is within the quota again
+    pub is_deferred: bool,
+}
+
+/// A window of the queue; borrowed from the queue while rendering.
+pub struct QueuePageView<'a> {
+    /// at most [`QUEUE_PAGE_SIZE`] tracks in ascending order
+    pub entries: Vec<QueueEntry<'a>>,
+    /// number of tracks which have been requested; might exceed the shown entries
+    pub selected_count: usize,
+    pub current_track_index: usize,
+    pub track_count: usize,
+}
+
+/// Rendered queue page which can be sent or used to replace an existing page.
+pub struct QueuePage {
+    embed: CreateEmbed,
+    components: CreateComponents,
+}
+
+impl QueuePageView<'_> {
+    pub fn render(&self) -> QueuePage {
+        QueuePage {
+            embed: self.render_embed(),
+            components: self.render_components(),
+        }
+    }
+
+    fn render_embed(&self) -> CreateEmbed {
+        let mut embed = CreateEmbed::default();
+        embed.title("Current queue").colour(COLOR_QUEUE);
+
+        let (Some(first), Some(last)) = (self.entries.first(), self.entries.last()) else {
+            embed.description(
+                "The playback queue is empty. Use the `enqueue` command to add some tracks.",
+            );
+            return embed;
+        };
+
+        for entry in &self.entries {
+            let name = if entry.index == self.current_track_index {
+                format!("▶ #{} · now playing", entry.index + 1)
+            } else if entry.is_deferred {
+                format!("⏳ #{} · held back by the quota", entry.index + 1)
+            } else {
+                format!("#{}", entry.index + 1)
+            };
+            embed.field(name, describe(entry.track), false);
+        }
+
+        let reached_end = last.index + 1 == self.track_count;
+        if reached_end && self.current_track_index == self.track_count {
+            embed.field("▶ END", "You've reached the end of the queue.", false);
+        }
+
+        let hidden = self.selected_count.saturating_sub(self.entries.len());
+        let footer = format!(
+            "Tracks #{}–#{} of {}{}",
+            first.index + 1,
+            last.index + 1,
+            self.track_count,
+            if hidden > 0 {
+                format!(" · {hidden} more selected track(s) not shown")
+            } else {
+                String::new()
+            }
+        );
+        embed.footer(|f| f.text(footer));
+        embed
+    }
+
+    fn render_components(&self) -> CreateComponents {
+        let mut components = CreateComponents::default();
+        let (Some(first), Some(last)) = (self.entries.first(), self.entries.last()) else {
+            return components;
+        };
+
+        let buttons = [
+            (
+                "◀",
+                first.index.saturating_sub(QUEUE_PAGE_SIZE),
+                first.index == 0,
+            ),
+            ("▶", last.index + 1, last.index + 1 >= self.track_count),
+        ];
+        components.create_action_row(|row| {
+            for (emoji, start, disabled) in buttons {
+                row.create_button(|button| {
+                    button
+                        .custom_id(format!("{PAGE_BUTTON_PREFIX}{start}"))
+                        .emoji(ReactionType::Unicode(emoji.to_owned()))
+                        .style(ButtonStyle::Secondary)
+                        .disabled(disabled)
+                });
+            }
+            row
+        });
+        components
+    }
+}
+
+/// e.g. `[artist - title](url) (3:20) [genre@120 bpm] @user`
+fn describe(track: &dyn Track) -> String {
+    let mut parts = vec![format!(
+        "[{} - {}]({})",
+        shorten(
+            &track
+                .artist()
+                .unwrap_or_else(|| "(unknown artist)".to_owned())
+        ),
+        shorten(
+            &track
+                .title()
+                .unwrap_or_else(|| "(unknown title)".to_owned())
+        ),
+        track.track_page_url()
+    )];
+    if let Some(duration) = track.duration() {
+        let seconds = duration.as_secs();
+        parts.push(format!("({}:{:0>2})", seconds / 60, seconds % 60));
+    }
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    match (track.genre(), track.bpm()) {
+        (None, None) => {}
+        (None, Some(bpm)) => parts.push(format!("[@{} bpm]", bpm.round().max(0.0) as u32)),
+        (Some(genre), None) => parts.push(format!("[{genre}]")),
+        (Some(genre), Some(bpm)) => {
+            parts.push(format!("[{}@{} bpm]", genre, bpm.round().max(0.0) as u32));
+        }
+    }
+    parts.push(format!("`@{}`", track.adding_user().name));
+    parts.join(" ")
+}
+
+fn shorten(name: &str) -> String {
+    if name.chars().count() > MAX_NAME_LENGTH {
+        let mut name: String = name.chars().take(MAX_NAME_LENGTH - 1).collect();
+        name.push('…');
+        name
+    } else {
+        name.to_owned()
+    }
+}
+
+impl QueuePage {
+    pub async fn send(&self, out: &MessageChannel) {
+        out.send_embed(self.embed.clone(), self.components.clone())
+            .await;
+    }
+
+    /// Replaces the page the component belongs to.
+    pub async fn replace(
+        &self,
+        http: &Http,
+        component: &MessageComponentInteraction,
+    ) -> CommandResult {
+        component
+            .create_interaction_response(http, |response| {
+                response
+                    .kind(InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|data| {
+                        data.set_embed(self.embed.clone())
+                            .set_components(self.components.clone())
+                    })
+            })
+            .await
+            .map_err(|err| CommandError::Discord(err.to_string()))
+    }
+}
@@ -0,0 +1,80 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use serenity::{http::Http, model::channel::GuildChannel};
+
+/// Discord limits the topic of a stage instance to this many characters.
+const MAX_TOPIC_LENGTH: usize = 120;
+
+/// Stage channel the bot is connected to.
+///
+/// Unlike in regular voice channels the bot needs to become a speaker before it can be heard, and
+/// the stage instance's topic is used to show what's currently playing.
+pub struct Stage {
+    channel: GuildChannel,
+    http: Arc<Http>,
+    /// whether the bot started the stage instance; only then it will end it again
+    started_instance: AtomicBool,
+}
+
+impl Stage {
+    pub fn new(channel: GuildChannel, http: Arc<Http>) -> Self {
+        Self {
+            channel,
+            http,
+            started_instance: AtomicBool::new(false),
+        }
+    }
+
+    /// Makes the bot a speaker; raises its hand if it isn't allowed to do that by itself.
+    pub async fn become_speaker(&self) {
+        let result = self
+            .channel
+            .edit_own_voice_state(&self.http, |voice_state| voice_state.suppress(false))
+            .await;
+        if let Err(err) = result {
+            eprintln!("failed to become a speaker: {err}; requesting to speak instead");
+            if let Err(err) = self
+                .channel
+                .edit_own_voice_state(&self.http, |voice_state| voice_state.request_to_speak(true))
+                .await
+            {
+                eprintln!("failed to request to speak: {err}");
+            }
+        }
+    }
+
+    /// Sets the topic of the stage instance; starts the stage instance if it isn't live yet.
+    pub async fn set_topic(&self, topic: &str) {
+        let topic: String = topic.chars().take(MAX_TOPIC_LENGTH).collect();
+        if self.channel.get_stage_instance(&self.http).await.is_ok() {
+            if let Err(err) = self
+                .channel
+                .edit_stage_instance(&self.http, |instance| instance.topic(&topic))
+                .await
+            {
+                eprintln!("failed to update the stage topic: {err}");
+            }
+        } else {
+            match self
+                .channel
+                .create_stage_instance(&self.http, |instance| instance.topic(&topic))
+                .await
+            {
+                Ok(_) => self.started_instance.store(true, Ordering::Relaxed),
+                Err(err) => eprintln!("failed to start a stage instance: {err}"),
+            }
+        }
+    }
+
+    /// Ends the stage instance if the bot started it.
+    pub async fn clean_up(&self) {
+        if self.started_instance.swap(false, Ordering::Relaxed) {
+            if let Err(err) = self.channel.delete_stage_instance(&self.http).await {
+                eprintln!("failed to end the stage instance: {err}");
+            }
+        }
+    }
+}
@@ -0,0 +1,442 @@
+use std::{collections::HashMap, convert::TryFrom, fmt, str::FromStr};
+
+use serenity::builder::CreateEmbed;
+
+use crate::play_history::{PlayOutcome, PlayRecord};
+
+/// Number of entries per ranking.
+const TOP_COUNT: usize = 10;
+
+/// Number of entries per ranking when all of them are shown at once.
+const OVERVIEW_TOP_COUNT: usize = 5;
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Days from the unix epoch until 9999-12-31; neither dates nor periods reach any further.
+const MAX_DAYS: u64 = 2_932_896;
+
+const COLOR_STATS: u32 = 0x00eb_459e;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatsCategory {
+    Tracks,
+    Artists,
+    Genres,
+    Enqueuers,
+    Time,
+}
+
+impl FromStr for StatsCategory {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tracks" => Ok(Self::Tracks),
+            "artists" => Ok(Self::Artists),
+            "genres" => Ok(Self::Genres),
+            "enqueuers" | "users" => Ok(Self::Enqueuers),
+            "time" => Ok(Self::Time),
+            _ => Err(format!("unknown statistics: {s}")),
+        }
+    }
+}
+
+/// Period the statistics cover.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatsPeriod {
+    /// up to now
+    LastDays(u64),
+    /// days since the unix epoch; both inclusive
+    Dates {
+        first: u64,
+        last: u64,
+    },
+    All,
+}
+
+impl FromStr for StatsPeriod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "today" => return Ok(Self::LastDays(1)),
+            "week" => return Ok(Self::LastDays(7)),
+            "month" => return Ok(Self::LastDays(30)),
+            "year" => return Ok(Self::LastDays(365)),
+            "all" => return Ok(Self::All),
+            _ => {}
+        }
+        if let Some(days) = s.strip_suffix('d') {
+            return days
+                .parse()
+                .ok()
+                .filter(|days| (1..=MAX_DAYS).contains(days))
+                .map(Self::LastDays)
+                .ok_or_else(|| format!("invalid number of days: {s}"));
+        }
+        let (first, last) = s.split_once("..").unwrap_or((s, s));
+        let first = parse_date(first).ok_or_else(|| format!("invalid date: {first}"))?;
+        let last = parse_date(last).ok_or_else(|| format!("invalid date: {last}"))?;
+        if first > last {
+            return Err(format!("the period {s} ends before it starts"));
+        }
+        Ok(Self::Dates { first, last })
+    }
+}
+
+impl StatsPeriod {
+    /// Start (inclusive) and end (exclusive) of the period in seconds since the unix epoch.
+    pub fn bounds(self, now: u64) -> (u64, u64) {
+        match self {
+            StatsPeriod::LastDays(days) => (
+                now.saturating_sub(days.saturating_mul(SECS_PER_DAY)),
+                u64::MAX,
+            ),
+            StatsPeriod::Dates { first, last } => (
+                first.saturating_mul(SECS_PER_DAY),
+                last.saturating_add(1).saturating_mul(SECS_PER_DAY),
+            ),
+            StatsPeriod::All => (0, u64::MAX),
+        }
+    }
+}
+
+impl fmt::Display for StatsPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            StatsPeriod::LastDays(1) => write!(f, "the last 24 hours"),
+            StatsPeriod::LastDays(days) => write!(f, "the last {days} days"),
+            StatsPeriod::Dates { first, last } if first == last => {
+                write!(f, "{}", format_date(first))
+            }
+            StatsPeriod::Dates { first, last } => {
+                write!(f, "{} – {}", format_date(first), format_date(last))
+            }
+            StatsPeriod::All => write!(f, "all time"),
+        }
+    }
+}
+
+/// Plays and listening time of a track, artist, genre or user.
+#[derive(Default)]
+struct Tally {
+    name: String,
+    plays: usize,
+    secs: u64,
+}
+
+/// Counts the plays per key; the most played come first.
+fn rank<'a>(
+    plays: &[&'a PlayRecord],
+    key: impl Fn(&'a PlayRecord) -> Option<(String, String)>,
+) -> Vec<Tally> {
+    let mut tallies: HashMap<String, Tally> = HashMap::new();
+    for play in plays {
+        if let Some((key, name)) = key(play) {
+            let tally = tallies.entry(key).or_default();
+            tally.name = name;
+            tally.plays += 1;
+            tally.secs += play.played_secs();
+        }
+    }
+    let mut tallies: Vec<Tally> = tallies.into_values().collect();
+    tallies.sort_by(|a, b| {
+        b.plays
+            .cmp(&a.plays)
+            .then(b.secs.cmp(&a.secs))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    tallies
+}
+
+/// e.g. `1. **name** · 4 plays · 12m`
+fn describe_ranking(tallies: &[Tally], count: usize) -> String {
+    if tallies.is_empty() {
+        return "(nothing)".to_owned();
+    }
+    tallies
+        .iter()
+        .take(count)
+        .enumerate()
+        .map(|(index, tally)| {
+            format!(
+                "{}. **{}** · {} play(s) · {}",
+                index + 1,
+                tally.name,
+                tally.plays,
+                format_duration(tally.secs)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn describe_time(plays: &[&PlayRecord]) -> String {
+    let total_secs: u64 = plays.iter().map(|play| play.played_secs()).sum();
+    let completed = plays
+        .iter()
+        .filter(|play| play.outcome == PlayOutcome::Completed)
+        .count();
+    let listeners: usize = plays.iter().map(|play| play.listeners).sum();
+    let average_listeners = listeners.checked_div(plays.len()).unwrap_or_default();
+    format!(
+        "⏱ {} of music · {} play(s) · {} completed · {} skipped · 👥 {} on average",
+        format_duration(total_secs),
+        plays.len(),
+        completed,
+        plays.len() - completed,
+        average_listeners
+    )
+}
+
+/// Renders the statistics of the plays of the given period; all of them if there's no category.
+pub fn render(
+    plays: &[PlayRecord],
+    category: Option<StatsCategory>,
+    period: StatsPeriod,
+) -> CreateEmbed {
+    let plays: Vec<&PlayRecord> = plays.iter().collect();
+
+    let mut embed = CreateEmbed::default();
+    embed
+        .title(format!("Stats for {period}"))
+        .colour(COLOR_STATS);
+    if plays.is_empty() {
+        embed.description("Nothing has been played during this period.");
+        return embed;
+    }
+
+    let tracks = || {
+        rank(&plays, |play| {
+            Some((play.track_page_url.clone(), play.caption()))
+        })
+    };
+    let artists = || {
+        rank(&plays, |play| {
+            play.artist.clone().map(|a| (a.to_lowercase(), a))
+        })
+    };
+    let genres = || {
+        rank(&plays, |play| {
+            play.genre.clone().map(|g| (g.to_lowercase(), g))
+        })
+    };
+    let enqueuers = || {
+        rank(&plays, |play| {
+            Some((
+                play.added_by.to_string(),
+                format!("@{}", play.added_by_name),
+            ))
+        })
+    };
+
+    match category {
+        None => {
+            embed.description(describe_time(&plays));
+            embed.field(
+                "Top tracks",
+                describe_ranking(&tracks(), OVERVIEW_TOP_COUNT),
+                false,
+            );
+            embed.field(
+                "Top artists",
+                describe_ranking(&artists(), OVERVIEW_TOP_COUNT),
+                false,
+            );
+            embed.field(
+                "Top genres",
+                describe_ranking(&genres(), OVERVIEW_TOP_COUNT),
+                false,
+            );
+            embed.field(
+                "Most active enqueuers",
+                describe_ranking(&enqueuers(), OVERVIEW_TOP_COUNT),
+                false,
+            );
+        }
+        Some(StatsCategory::Tracks) => {
+            embed.field("Top tracks", describe_ranking(&tracks(), TOP_COUNT), false);
+        }
+        Some(StatsCategory::Artists) => {
+            embed.field(
+                "Top artists",
+                describe_ranking(&artists(), TOP_COUNT),
+                false,
+            );
+        }
+        Some(StatsCategory::Genres) => {
+            embed.field("Top genres", describe_ranking(&genres(), TOP_COUNT), false);
+        }
+        Some(StatsCategory::Enqueuers) => {
+            embed.field(
+                "Most active enqueuers",
+                describe_ranking(&enqueuers(), TOP_COUNT),
+                false,
+            );
+        }
+        Some(StatsCategory::Time) => {
+            embed.description(describe_time(&plays));
+        }
+    }
+    embed
+}
+
+/// e.g. `2h 05m` or `12m`
+fn format_duration(secs: u64) -> String {
+    let minutes = secs / 60;
+    if minutes >= 60 {
+        format!("{}h {:0>2}m", minutes / 60, minutes % 60)
+    } else {
+        format!("{minutes}m")
+    }
+}
+
+/// Parses `YYYY-MM-DD` into days since the unix epoch.
+fn parse_date(date: &str) -> Option<u64> {
+    let mut parts = date.trim().splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    if !(1970..=9999).contains(&year) {
+        return None;
+    }
+    let is_leap_year = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year => 29,
+        2 => 28,
+        _ => return None,
+    };
+    if !(1..=days_in_month).contains(&day) {
+        return None;
+    }
+    // see http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    u64::try_from(era * 146_097 + day_of_era - 719_468).ok()
+}
+
+/// Formats days since the unix epoch as `YYYY-MM-DD`.
+fn format_date(days: u64) -> String {
+    // see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = i64::try_from(days).unwrap_or_default() + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_are_parsed() {
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("2026-10-18"), Some(20_744));
+        assert_eq!(parse_date("2024-02-29"), Some(19_782));
+        assert_eq!(parse_date(" 2000-02-29 "), Some(11_016));
+    }
+
+    #[test]
+    fn invalid_dates_are_rejected() {
+        assert_eq!(parse_date("2026-02-31"), None);
+        assert_eq!(parse_date("2026-02-29"), None);
+        assert_eq!(parse_date("1900-02-29"), None);
+        assert_eq!(parse_date("2026-04-31"), None);
+        assert_eq!(parse_date("2026-13-01"), None);
+        assert_eq!(parse_date("2026-00-10"), None);
+        assert_eq!(parse_date("2026-01-00"), None);
+        assert_eq!(parse_date("1969-12-31"), None);
+        assert_eq!(parse_date("2026-01"), None);
+        assert_eq!(parse_date("yesterday"), None);
+    }
+
+    #[test]
+    fn dates_are_formatted() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(19_782), "2024-02-29");
+        for date in ["2000-02-29", "2026-10-18", "2099-12-31"] {
+            assert_eq!(format_date(parse_date(date).unwrap()), date);
+        }
+    }
+
+    #[test]
+    fn periods_are_parsed() {
+        assert_eq!("today".parse(), Ok(StatsPeriod::LastDays(1)));
+        assert_eq!("week".parse(), Ok(StatsPeriod::LastDays(7)));
+        assert_eq!("all".parse(), Ok(StatsPeriod::All));
+        assert_eq!("14d".parse(), Ok(StatsPeriod::LastDays(14)));
+        assert_eq!(
+            "2026-10-18".parse(),
+            Ok(StatsPeriod::Dates {
+                first: 20_744,
+                last: 20_744
+            })
+        );
+        assert_eq!(
+            "2026-10-01..2026-10-18".parse(),
+            Ok(StatsPeriod::Dates {
+                first: 20_727,
+                last: 20_744
+            })
+        );
+    }
+
+    #[test]
+    fn out_of_range_periods_are_rejected() {
+        for period in [
+            "300000000000000d",
+            "18446744073709551615d",
+            "2932897d",
+            "900000000000-01-01",
+            "10000-01-01",
+            "2026-01-01..900000000000-01-01",
+        ] {
+            assert!(period.parse::<StatsPeriod>().is_err(), "{}", period);
+        }
+        assert_eq!("2932896d".parse(), Ok(StatsPeriod::LastDays(MAX_DAYS)));
+        assert_eq!(parse_date("9999-12-31"), Some(MAX_DAYS));
+    }
+
+    #[test]
+    fn bounds_of_long_periods_dont_overflow() {
+        let now = 1_000_000;
+        assert_eq!(StatsPeriod::LastDays(MAX_DAYS).bounds(now), (0, u64::MAX));
+        let (start, end) = StatsPeriod::Dates {
+            first: MAX_DAYS,
+            last: MAX_DAYS,
+        }
+        .bounds(now);
+        assert_eq!(end - start, SECS_PER_DAY);
+        assert_eq!(
+            StatsPeriod::Dates {
+                first: u64::MAX,
+                last: u64::MAX
+            }
+            .bounds(now),
+            (u64::MAX, u64::MAX)
+        );
+    }
+
+    #[test]
+    fn invalid_periods_are_rejected() {
+        for period in ["0d", "xd", "2026-02-31", "2026-10-18..2026-10-01", "soon"] {
+            assert!(period.parse::<StatsPeriod>().is_err(), "{}", period);
+        }
+    }
+}
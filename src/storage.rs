@@ -0,0 +1,83 @@
+use lazy_static::lazy_static;
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex as StdMutex,
+    },
+};
+
+use json::JsonValue;
+use serenity::model::id::{GuildId, UserId};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+/// environment variable pointing to the directory where the bot keeps its data
+pub const ENV_DATA_DIR: &str = "AUDIOLOOT_DATA_DIR";
+
+const DEFAULT_DATA_DIR: &str = "data";
+
+lazy_static! {
+    /// One lock per document which is being read and written again.
+    static ref DOCUMENT_LOCKS: StdMutex<HashMap<PathBuf, Arc<Mutex<()>>>> = StdMutex::default();
+}
+
+/// Makes temporary files unique, so concurrent writes can't clobber each other.
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Directory holding everything the bot keeps; e.g. `data`.
+pub fn data_dir() -> PathBuf {
+    PathBuf::from(env::var(ENV_DATA_DIR).unwrap_or_else(|_| DEFAULT_DATA_DIR.to_owned()))
+}
+
+/// Directory holding everything the bot keeps about a guild; e.g. `data/guilds/1234`.
+pub fn guild_dir(guild_id: GuildId) -> PathBuf {
+    data_dir().join("guilds").join(guild_id.to_string())
+}
+
+/// Directory holding everything the bot keeps about a user across guilds; e.g. `data/users/1234`.
+pub fn user_dir(user_id: UserId) -> PathBuf {
+    data_dir().join("users").join(user_id.to_string())
+}
+
+/// Waits until nobody else is reading and writing the document; the document is locked until the
+/// guard is dropped.
+pub async fn lock_document(path: &Path) -> OwnedMutexGuard<()> {
+    let lock = DOCUMENT_LOCKS
+        .lock()
+        .expect("failed to unlock the document locks")
+        .entry(path.to_owned())
+        .or_default()
+        .clone();
+    lock.lock_owned().await
+}
+
+/// Reads a JSON document; `None` if it doesn't exist (yet).
+pub fn read_document(path: &Path) -> io::Result<Option<JsonValue>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    json::parse(&content)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Replaces a JSON document; a crash while writing leaves the previous version intact.
+pub fn write_document(path: &Path, document: &JsonValue) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut temp_name = path.file_name().unwrap_or_default().to_owned();
+    temp_name.push(format!(
+        ".{}-{}.tmp",
+        process::id(),
+        TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let temp_path = path.with_file_name(temp_name);
+    fs::write(&temp_path, document.pretty(2))?;
+    fs::rename(temp_path, path)
+}
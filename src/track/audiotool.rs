@@ -1,13 +1,17 @@
 use std::{string::ToString, time::Duration};
 
+use futures::{future::BoxFuture, stream, FutureExt, StreamExt};
 use json::JsonValue;
 use regex::Regex;
 use serenity::model::prelude::User;
 //use serenity::prelude::TypeMapKey;
 
-use crate::AUDIOTOOL_HTTP_CLIENT;
+use crate::{audiotool::RequestError, AUDIOTOOL_HTTP_CLIENT};
 
-use super::{Track, TrackRefDispatcher};
+use super::{
+    CommentsResult, RemixParent, Track, TrackComment, TrackRefDispatcher, TrackStats, TrackStream,
+    MAX_CONCURRENT_RESOLVES,
+};
 
 // struct MetaData {
 //     duration: Option<f64>,
@@ -33,19 +37,19 @@ pub struct AudiotoolTrack {
     comment: Option<String>,
 
     //_construction_url: String,
-    //track_key: String,
+    track_key: String,
     track_page_url: String,
-    pub track_playback_url: String,
 
     duration: Option<Duration>,
     name: Option<String>,
     cover_url: Option<String>,
     bpm: Option<f64>,
     genre_key: Option<String>,
-    //user_key: Option<String>,
+    user_key: Option<String>,
     user_name: Option<String>,
     created: Option<String>,
-    //comment_count: Option<u32>,
+    stats: Option<TrackStats>,
+    remix_of: Option<RemixParent>,
     adding_user: User,
 }
 
@@ -55,21 +59,29 @@ impl AudiotoolTrack {
         comment: Option<String>,
         user: &User,
     ) -> Result<Self, String> {
-        let track_page_url = format!("https://www.audiotool.com/track/{track_key}/");
+        let track_page_url = format!("{}/track/{track_key}/", AUDIOTOOL_HTTP_CLIENT.web_url());
 
         let duration;
         let name;
         let cover_url;
         let bpm;
         let genre_name;
-        //let user_key;
+        let user_key;
         let user_name;
         let created;
-        //let comment_count;
+        let stats;
+        let remix_of;
+
+        let details = match AUDIOTOOL_HTTP_CLIENT.request_track_details(track_key).await {
+            Ok(details) => Some(details),
+            // without access to the details the track won't be playable either
+            Err(RequestError::Unauthorized(err)) => return Err(err),
+            Err(RequestError::Failed(_)) => None,
+        };
 
         // e.g. https://www.audiotool.com/track/5zcqbylu5mb/details.json
         #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-        if let Ok(details) = AUDIOTOOL_HTTP_CLIENT.request_track_details(track_key).await {
+        if let Some(details) = details {
             duration = details["duration"]
                 .as_f64()
                 .map(|millis| Duration::from_millis(millis.round().max(0.0) as u64));
@@ -77,53 +89,71 @@ impl AudiotoolTrack {
             cover_url = details["coverUrl"].as_str().map(ToOwned::to_owned);
             bpm = details["bpm"].as_f64();
             genre_name = details["genreName"].as_str().map(ToOwned::to_owned);
-            //user_key = details["user"]["key"].as_str().map(|s| s.to_owned());
+            user_key = details["user"]["key"].as_str().map(ToOwned::to_owned);
             user_name = details["user"]["name"].as_str().map(ToOwned::to_owned);
             created = details["created"].as_str().map(ToOwned::to_owned);
-            //comment_count = details["comments"].as_u32();
+            stats = Some(TrackStats {
+                plays: details["plays"].as_u64(),
+                favourites: details["favorites"].as_u64(),
+                comments: details["comments"].as_u64(),
+            });
+            remix_of = details["remixOf"]["key"].as_str().map(|key| RemixParent {
+                title: details["remixOf"]["name"].as_str().map(ToOwned::to_owned),
+                artist: details["remixOf"]["user"]["name"]
+                    .as_str()
+                    .map(ToOwned::to_owned),
+                track_page_url: format!("{}/track/{key}/", AUDIOTOOL_HTTP_CLIENT.web_url()),
+            });
         } else {
             duration = None;
             name = None;
             cover_url = None;
             bpm = None;
             genre_name = None;
-            //user_key = None;
+            user_key = None;
             user_name = None;
             created = None;
-            //comment_count = None;
+            stats = None;
+            remix_of = None;
         }
 
-        let cookies = AUDIOTOOL_HTTP_CLIENT.cular_cookie();
-
-        let cular_cookie = cookies
-            .get("cular-session")
-            .ok_or("cular cookie is invalid")?;
-        let track_playback_url = format!(
-            "https://api.audiotool.com/track/{track_key}/play.ogg?platform=1&ref=website&X-Cular-Session={cular_cookie}"
-        );
-
         Ok(Self {
             //_construction_url: construction_url.to_owned(),
-            //track_key: track_key.to_owned(),
+            track_key: track_key.to_owned(),
             comment,
             track_page_url,
-            track_playback_url,
 
             duration,
             name,
             cover_url,
             bpm,
             genre_key: genre_name,
-            //user_key,
+            user_key,
             user_name,
             created,
+            stats,
+            remix_of,
             adding_user: user.clone(),
-            //comment_count,
-
             // chan_id,
             // http,
         })
     }
+
+    /// Resolves the given tracks concurrently while retaining their order.
+    fn resolve_track_keys(track_keys: Vec<(String, Option<String>)>, user: &User) -> TrackStream {
+        let user = user.clone();
+        stream::iter(track_keys)
+            .map(move |(track_key, comment)| {
+                let user = user.clone();
+                async move {
+                    Self::try_from_track_key(&track_key, comment, &user)
+                        .await
+                        .map(|track| Box::new(track) as Box<dyn Track>)
+                }
+            })
+            .buffered(MAX_CONCURRENT_RESOLVES)
+            .boxed()
+    }
 }
 
 pub struct AudiotoolTrackRefDispatcher {}
@@ -135,9 +165,12 @@ impl TrackRefDispatcher for AudiotoolTrackRefDispatcher {
         track_ref: &str,
         comment: Option<String>,
         user: &User,
-    ) -> Option<Vec<Result<Box<dyn Track>, String>>> {
-        let track_url_regex = Regex::new(r"http[s]?://www.audiotool.com/track/([^/]+)")
-            .unwrap_or_else(|err| panic!("failed fo create regex: {}", err));
+    ) -> Option<TrackStream> {
+        let track_url_regex = Regex::new(&format!(
+            r"{}/track/([^/]+)",
+            AUDIOTOOL_HTTP_CLIENT.web_url_pattern()
+        ))
+        .unwrap_or_else(|err| panic!("failed fo create regex: {}", err));
 
         let track_key = match track_url_regex.captures(track_ref) {
             Some(cap) => cap[1].to_owned(),
@@ -146,14 +179,10 @@ impl TrackRefDispatcher for AudiotoolTrackRefDispatcher {
             }
         };
 
-        let mut results = Vec::new();
-        let track = AudiotoolTrack::try_from_track_key(&track_key, comment, user)
-            .await
-            .map(|track| Box::new(track) as Box<dyn Track>);
-
-        results.push(track);
-
-        Some(results)
+        Some(AudiotoolTrack::resolve_track_keys(
+            vec![(track_key, comment)],
+            user,
+        ))
     }
 }
 
@@ -168,14 +197,14 @@ impl TrackRefDispatcher for AudiotoolSingleChartsDispatcher {
         track_ref: &str,
         comment: Option<String>,
         user: &User,
-    ) -> Option<Vec<Result<Box<dyn Track>, String>>> {
+    ) -> Option<TrackStream> {
         // https://www.audiotool.com/genre/trap/charts/2021-35
 
         if track_ref != "at:single-charts" {
             return None;
         }
 
-        let mut results = Vec::new();
+        let mut track_keys = Vec::new();
 
         if let Ok(charts) = AUDIOTOOL_HTTP_CLIENT.request_single_charts_details().await {
             if let JsonValue::Array(tracks) = &charts["tracks"] {
@@ -184,19 +213,14 @@ impl TrackRefDispatcher for AudiotoolSingleChartsDispatcher {
                         .clone()
                         .unwrap_or_else(|| format!("#{} in Single Charts", index + 1));
                     if let Some(track_key) = track_detail["key"].as_str() {
-                        let track =
-                            AudiotoolTrack::try_from_track_key(track_key, Some(comment), user)
-                                .await
-                                .map(|track| Box::new(track) as Box<dyn Track>);
-
-                        results.push(track);
+                        track_keys.push((track_key.to_owned(), Some(comment)));
                     }
                 }
             }
         }
-        results.reverse();
+        track_keys.reverse();
 
-        Some(results)
+        Some(AudiotoolTrack::resolve_track_keys(track_keys, user))
     }
 }
 
@@ -211,12 +235,14 @@ impl TrackRefDispatcher for AudiotoolGenreChartsDispatcher {
         track_ref: &str,
         comment: Option<String>,
         user: &User,
-    ) -> Option<Vec<Result<Box<dyn Track>, String>>> {
+    ) -> Option<TrackStream> {
         // https://www.audiotool.com/genre/trap/charts/2021-35
 
-        let track_url_regex =
-            Regex::new(r"http[s]?://www.audiotool.com/genre/([^/]+)/charts/(\d{4}-\d{2})")
-                .unwrap_or_else(|err| panic!("failed fo create regex: {}", err));
+        let track_url_regex = Regex::new(&format!(
+            r"{}/genre/([^/]+)/charts/(\d{{4}}-\d{{2}})",
+            AUDIOTOOL_HTTP_CLIENT.web_url_pattern()
+        ))
+        .unwrap_or_else(|err| panic!("failed fo create regex: {}", err));
 
         let (genre_key, date) = match track_url_regex.captures(track_ref) {
             Some(cap) => (cap[1].to_owned(), cap[2].to_owned()),
@@ -225,7 +251,7 @@ impl TrackRefDispatcher for AudiotoolGenreChartsDispatcher {
             }
         };
 
-        let mut results = Vec::new();
+        let mut track_keys = Vec::new();
 
         if let Ok(charts) = AUDIOTOOL_HTTP_CLIENT
             .request_genre_charts_details(&genre_key, &date)
@@ -241,19 +267,14 @@ impl TrackRefDispatcher for AudiotoolGenreChartsDispatcher {
                         .clone()
                         .unwrap_or_else(|| format!("#{} in {}", index + 1, name));
                     if let Some(track_key) = track_detail["key"].as_str() {
-                        let track =
-                            AudiotoolTrack::try_from_track_key(track_key, Some(comment), user)
-                                .await
-                                .map(|track| Box::new(track) as Box<dyn Track>);
-
-                        results.push(track);
+                        track_keys.push((track_key.to_owned(), Some(comment)));
                     }
                 }
             }
         }
-        results.reverse();
+        track_keys.reverse();
 
-        Some(results)
+        Some(AudiotoolTrack::resolve_track_keys(track_keys, user))
     }
 }
 
@@ -268,11 +289,14 @@ impl TrackRefDispatcher for AudiotoolAlbumDispatcher {
         track_ref: &str,
         comment: Option<String>,
         user: &User,
-    ) -> Option<Vec<Result<Box<dyn Track>, String>>> {
+    ) -> Option<TrackStream> {
         // https://www.audiotool.com/genre/trap/charts/2021-35
 
-        let track_url_regex = Regex::new(r"http[s]?://www.audiotool.com/album/([^/]+)")
-            .unwrap_or_else(|err| panic!("failed fo create regex: {}", err));
+        let track_url_regex = Regex::new(&format!(
+            r"{}/album/([^/]+)",
+            AUDIOTOOL_HTTP_CLIENT.web_url_pattern()
+        ))
+        .unwrap_or_else(|err| panic!("failed fo create regex: {}", err));
 
         let album_key = match track_url_regex.captures(track_ref) {
             Some(cap) => cap[1].to_owned(),
@@ -281,7 +305,7 @@ impl TrackRefDispatcher for AudiotoolAlbumDispatcher {
             }
         };
 
-        let mut results = Vec::new();
+        let mut track_keys = Vec::new();
 
         if let Ok(charts) = AUDIOTOOL_HTTP_CLIENT
             .request_album_details(&album_key)
@@ -297,18 +321,13 @@ impl TrackRefDispatcher for AudiotoolAlbumDispatcher {
                         .clone()
                         .unwrap_or_else(|| format!("#{} in {}", index + 1, name));
                     if let Some(track_key) = track_detail["key"].as_str() {
-                        let track =
-                            AudiotoolTrack::try_from_track_key(track_key, Some(comment), user)
-                                .await
-                                .map(|track| Box::new(track) as Box<dyn Track>);
-
-                        results.push(track);
+                        track_keys.push((track_key.to_owned(), Some(comment)));
                     }
                 }
             }
         }
 
-        Some(results)
+        Some(AudiotoolTrack::resolve_track_keys(track_keys, user))
     }
 }
 
@@ -318,8 +337,16 @@ impl Track for AudiotoolTrack {
         &self.track_page_url
     }
 
-    fn playback_url(&self) -> &str {
-        &self.track_playback_url
+    fn playback_url(&self) -> Result<String, String> {
+        let cookies = AUDIOTOOL_HTTP_CLIENT.cular_cookie();
+        let cular_cookie = cookies
+            .get("cular-session")
+            .ok_or("cular cookie is invalid")?;
+        Ok(format!(
+            "{}/track/{}/play.ogg?platform=1&ref=website&X-Cular-Session={cular_cookie}",
+            AUDIOTOOL_HTTP_CLIENT.api_url(),
+            self.track_key
+        ))
     }
 
     fn duration(&self) -> Option<Duration> {
@@ -358,6 +385,42 @@ impl Track for AudiotoolTrack {
         &self.adding_user
     }
 
+    fn artist_url(&self) -> Option<String> {
+        self.user_key
+            .as_ref()
+            .map(|user_key| format!("{}/user/{user_key}/", AUDIOTOOL_HTTP_CLIENT.web_url()))
+    }
+
+    fn stats(&self) -> Option<TrackStats> {
+        self.stats
+    }
+
+    fn remix_of(&self) -> Option<RemixParent> {
+        self.remix_of.clone()
+    }
+
+    fn latest_comments(&self, count: usize) -> Option<BoxFuture<'static, CommentsResult>> {
+        let track_key = self.track_key.clone();
+        Some(
+            async move {
+                let comments = AUDIOTOOL_HTTP_CLIENT
+                    .request_track_comments(&track_key, count)
+                    .await
+                    .map_err(|err| err.to_string())?;
+                Ok(comments["comments"]
+                    .members()
+                    .filter_map(|comment| {
+                        Some(TrackComment {
+                            author: comment["user"]["name"].as_str().map(ToOwned::to_owned),
+                            text: comment["text"].as_str()?.to_owned(),
+                        })
+                    })
+                    .collect())
+            }
+            .boxed(),
+        )
+    }
+
     // fn channel_id(&self) -> ChannelId {
     //     self.chan_id
     // }
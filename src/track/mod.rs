@@ -1,11 +1,23 @@
-use std::{string::ToString, time::Duration};
+use std::{fmt, string::ToString, time::Duration};
 
+use futures::{future::BoxFuture, stream::BoxStream};
 use serenity::model::prelude::User;
 
+use self::{
+    audiotool::{
+        AudiotoolAlbumDispatcher, AudiotoolGenreChartsDispatcher, AudiotoolSingleChartsDispatcher,
+        AudiotoolTrackRefDispatcher,
+    },
+    youtube::YoutubeTrackRefDispatcher,
+};
+
 pub mod audiotool;
 pub mod selection;
 pub mod youtube;
 
+#[cfg(test)]
+mod tests;
+
 #[serenity::async_trait]
 pub trait Track: Send + Sync {
     fn caption(&self) -> String {
@@ -44,7 +56,9 @@ pub trait Track: Send + Sync {
     }
 
     fn track_page_url(&self) -> &str;
-    fn playback_url(&self) -> &str;
+    /// URL to stream the track from; built right before the playback starts, as it may contain a
+    /// session which expires in the meantime.
+    fn playback_url(&self) -> Result<String, String>;
     fn duration(&self) -> Option<Duration>;
     fn title(&self) -> Option<String>;
     fn cover_url(&self) -> Option<String>;
@@ -54,8 +68,78 @@ pub trait Track: Send + Sync {
     fn created(&self) -> Option<String>;
     fn comment(&self) -> Option<String>;
     fn adding_user(&self) -> &User;
+
+    /// profile page of the track's artist
+    fn artist_url(&self) -> Option<String> {
+        None
+    }
+
+    fn stats(&self) -> Option<TrackStats> {
+        None
+    }
+
+    /// the track this one is a remix of
+    fn remix_of(&self) -> Option<RemixParent> {
+        None
+    }
+
+    /// Fetches up to `count` of the most recent comments which have been posted on the track page.
+    ///
+    /// Returns `None` if the track's source doesn't support comments. The returned future doesn't
+    /// borrow the track, so it can be awaited without holding on to the queue.
+    fn latest_comments(&self, _count: usize) -> Option<BoxFuture<'static, CommentsResult>> {
+        None
+    }
+}
+
+/// Counters shown on the track page at the time the track has been enqueued.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TrackStats {
+    pub plays: Option<u64>,
+    pub favourites: Option<u64>,
+    pub comments: Option<u64>,
+}
+
+impl fmt::Display for TrackStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count =
+            |count: Option<u64>| count.map_or_else(|| "?".to_owned(), |count| count.to_string());
+        write!(
+            f,
+            "▶ {} plays · ♥ {} favourites · 💬 {} comments",
+            count(self.plays),
+            count(self.favourites),
+            count(self.comments)
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RemixParent {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub track_page_url: String,
 }
 
+#[derive(Clone, Debug)]
+pub struct TrackComment {
+    pub author: Option<String>,
+    pub text: String,
+}
+
+pub type CommentsResult = Result<Vec<TrackComment>, String>;
+
+/// maximum number of tracks of a single track reference which will be resolved at the same time
+pub const MAX_CONCURRENT_RESOLVES: usize = 8;
+
+pub type TrackResult = Result<Box<dyn Track>, String>;
+
+/// Tracks in the order they should be enqueued.
+///
+/// Tracks become available as soon as they have been resolved, so that playback can start while
+/// the remaining tracks are still loading.
+pub type TrackStream = BoxStream<'static, TrackResult>;
+
 #[serenity::async_trait]
 pub trait TrackRefDispatcher: Send + Sync {
     async fn dispatch(
@@ -63,5 +147,31 @@ pub trait TrackRefDispatcher: Send + Sync {
         track_ref: &str,
         comment: Option<String>,
         user: &User,
-    ) -> Option<Vec<Result<Box<dyn Track>, String>>>;
+    ) -> Option<TrackStream>;
+}
+
+/// Offers the track reference to all known dispatchers in turn.
+///
+/// Returns the tracks of the first dispatcher which recognized the reference or `None` if the
+/// reference couldn't be interpreted at all.
+pub async fn dispatch(
+    track_ref: &str,
+    comment: Option<String>,
+    user: &User,
+) -> Option<TrackStream> {
+    let dispatchers: Vec<Box<dyn TrackRefDispatcher>> = vec![
+        Box::new(AudiotoolTrackRefDispatcher {}),
+        Box::new(AudiotoolSingleChartsDispatcher {}),
+        Box::new(AudiotoolGenreChartsDispatcher {}),
+        Box::new(AudiotoolAlbumDispatcher {}),
+        Box::new(YoutubeTrackRefDispatcher {}),
+    ];
+
+    for dispatcher in &dispatchers {
+        if let Some(tracks) = dispatcher.dispatch(track_ref, comment.clone(), user).await {
+            return Some(tracks);
+        }
+    }
+
+    None
 }
@@ -0,0 +1,584 @@
+//! Runs the track dispatchers against a local stand-in for the Audiotool servers.
+//!
+//! The stand-in is a minimal HTTP server which is started once per test binary. Its address is
+//! handed to the global `AUDIOTOOL_HTTP_CLIENT` via the same environment variables that would be
+//! used to point the bot at a mock server in production. The bot logs in with a mock account, so
+//! the stand-in can serve private tracks as well.
+
+use std::{
+    env,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use futures::StreamExt;
+use serenity::model::prelude::User;
+
+use crate::{
+    audiotool::{
+        AudiotoolHttpClient, AuthState, CircuitState, RequestPolicy, ENV_API_URL, ENV_PASSWORD,
+        ENV_USERNAME, ENV_WEB_URL,
+    },
+    AUDIOTOOL_HTTP_CLIENT,
+};
+
+use super::{
+    audiotool::{
+        AudiotoolAlbumDispatcher, AudiotoolGenreChartsDispatcher, AudiotoolSingleChartsDispatcher,
+        AudiotoolTrackRefDispatcher,
+    },
+    Track, TrackRefDispatcher,
+};
+
+/// session handed out after logging in
+const SESSION: &str = "mock-session";
+/// session handed out to visitors who aren't logged in
+const ANONYMOUS_SESSION: &str = "anonymous-session";
+const USERNAME: &str = "mock-user";
+const PASSWORD: &str = "mock-password";
+
+static MOCK_URL: OnceLock<String> = OnceLock::new();
+
+/// number of requests for tracks whose server is broken
+static BROKEN_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+/// number of login attempts with a wrong password
+static REJECTED_LOGINS: AtomicUsize = AtomicUsize::new(0);
+
+fn mock_url() -> &'static str {
+    MOCK_URL.get_or_init(start_mock_server)
+}
+
+fn start_mock_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind mock server");
+    let url = format!(
+        "http://{}",
+        listener.local_addr().expect("mock server has no address")
+    );
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            thread::spawn(move || serve(stream));
+        }
+    });
+
+    env::set_var(ENV_WEB_URL, &url);
+    env::set_var(ENV_API_URL, format!("{url}/api"));
+    env::set_var(ENV_USERNAME, USERNAME);
+    env::set_var(ENV_PASSWORD, PASSWORD);
+    url
+}
+
+fn serve(mut stream: TcpStream) {
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(stream.try_clone().expect("failed to clone stream"));
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    let mut cookie = None;
+    let mut content_length = 0;
+    let mut header = String::new();
+    while reader.read_line(&mut header).is_ok_and(|len| len > 2) {
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            match name.to_ascii_lowercase().as_str() {
+                "cookie" => cookie = Some(value.to_owned()),
+                "content-length" => content_length = value.parse().unwrap_or_default(),
+                _ => {}
+            }
+        }
+        header.clear();
+    }
+    let mut body = vec![0; content_length];
+    if reader.read_exact(&mut body).is_err() {
+        return;
+    }
+
+    let mut parts = request_line.split(' ');
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    let path = path.split('?').next().unwrap_or_default();
+    let request = MockRequest {
+        method,
+        path,
+        logged_in: cookie
+            .as_ref()
+            .is_some_and(|cookie| cookie.contains(&format!("cular-session={SESSION}"))),
+        has_session: cookie
+            .as_ref()
+            .is_some_and(|cookie| cookie.contains("cular-session=")),
+        body: &String::from_utf8_lossy(&body),
+    };
+    let (status, extra_headers, body) = respond(&request);
+
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n{extra_headers}\r\n{body}",
+        body.len()
+    );
+}
+
+struct MockRequest<'a> {
+    method: &'a str,
+    path: &'a str,
+    /// whether the request carries the session of a logged in user
+    logged_in: bool,
+    has_session: bool,
+    body: &'a str,
+}
+
+fn respond(request: &MockRequest) -> (&'static str, String, String) {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    match segments[..] {
+        [""] if request.has_session => ("200 OK", String::new(), String::new()),
+        [""] => (
+            "200 OK",
+            format!("Set-Cookie: cular-session={ANONYMOUS_SESSION};Path=/\r\n"),
+            String::new(),
+        ),
+        ["login"] if request.method == "POST" => {
+            if request.body == format!("username={USERNAME}&password={PASSWORD}") {
+                (
+                    "200 OK",
+                    format!("Set-Cookie: cular-session={SESSION};Path=/\r\n"),
+                    String::new(),
+                )
+            } else {
+                REJECTED_LOGINS.fetch_add(1, Ordering::SeqCst);
+                ("401 Unauthorized", String::new(), String::new())
+            }
+        }
+        ["track", key, "details.json"]
+            if key.starts_with("forbidden")
+                || (key.starts_with("private") && !request.logged_in) =>
+        {
+            ("403 Forbidden", String::new(), String::new())
+        }
+        ["track", key, "details.json"] if key.starts_with("broken") => {
+            BROKEN_REQUESTS.fetch_add(1, Ordering::SeqCst);
+            ("503 Service Unavailable", String::new(), String::new())
+        }
+        ["track", key, "details.json"] if !key.starts_with("missing") => {
+            delay(key);
+            let mut details = json::object! {
+                key: key,
+                name: format!("Track {key}"),
+                duration: 123_456,
+                coverUrl: format!("{}/cover/{key}.jpg", mock_url()),
+                bpm: 128.0,
+                genreName: "House",
+                user: json::object! { key: "artist", name: "Some Artist" },
+                created: "2021-09-01",
+                plays: 1234,
+                favorites: 56,
+                comments: 2,
+            };
+            if let Some(parent) = key.strip_prefix("remix-of-") {
+                details["remixOf"] = json::object! {
+                    key: parent,
+                    name: format!("Track {parent}"),
+                    user: json::object! { key: "original", name: "Original Artist" },
+                };
+            }
+            ("200 OK", String::new(), details.dump())
+        }
+        ["api", "track", _key, "comments.json"] => (
+            "200 OK",
+            String::new(),
+            json::object! {
+                comments: [
+                    json::object! { user: json::object! { name: "Fan" }, text: "love it" },
+                    json::object! { user: json::object! { name: "Critic" }, text: "too loud" },
+                ],
+            }
+            .dump(),
+        ),
+        ["api", "tracks", "charts.json"] => ("200 OK", String::new(), track_list("chart", None)),
+        ["api", "genre", genre, "charts", _date] => (
+            "200 OK",
+            String::new(),
+            track_list(genre, Some(format!("{genre} top tracks"))),
+        ),
+        ["api", "album", album, "tracks.json"] => (
+            "200 OK",
+            String::new(),
+            track_list(album, Some(format!("Album {album}"))),
+        ),
+        _ => ("404 Not Found", String::new(), String::new()),
+    }
+}
+
+/// Tracks of the `slow` album take longer to resolve the earlier they appear in the album.
+fn delay(key: &str) {
+    if let Some(index) = key
+        .strip_prefix("slow-")
+        .and_then(|index| index.parse::<u64>().ok())
+    {
+        thread::sleep(Duration::from_millis(100 * (4 - index)));
+    }
+}
+
+fn track_list(prefix: &str, name: Option<String>) -> String {
+    let mut list = json::object! {
+        tracks: (1..=3).map(|index| json::object! { key: format!("{prefix}-{index}") }).collect::<Vec<_>>(),
+    };
+    if let Some(name) = name {
+        list["name"] = name.into();
+    }
+    list.dump()
+}
+
+/// Makes sure the mock server is running and the client is logged in.
+async fn setup() -> &'static str {
+    let url = mock_url();
+    AUDIOTOOL_HTTP_CLIENT.keep_alive().await;
+    url
+}
+
+async fn dispatch(
+    dispatcher: &dyn TrackRefDispatcher,
+    track_ref: &str,
+    comment: Option<&str>,
+) -> Option<Vec<Box<dyn Track>>> {
+    let tracks = dispatcher
+        .dispatch(track_ref, comment.map(ToOwned::to_owned), &User::default())
+        .await?;
+    Some(
+        tracks
+            .map(|track| track.unwrap_or_else(|err| panic!("failed to resolve track: {}", err)))
+            .collect()
+            .await,
+    )
+}
+
+fn comments(tracks: &[Box<dyn Track>]) -> Vec<String> {
+    tracks
+        .iter()
+        .map(|track| track.comment().unwrap_or_default())
+        .collect()
+}
+
+#[tokio::test]
+async fn track_dispatcher_resolves_details() {
+    let url = setup().await;
+
+    let tracks = dispatch(
+        &AudiotoolTrackRefDispatcher {},
+        &format!("{url}/track/abc/"),
+        Some("nice one"),
+    )
+    .await
+    .expect("track URL not recognized");
+
+    assert_eq!(tracks.len(), 1);
+    let track = &tracks[0];
+    assert_eq!(track.title().as_deref(), Some("Track abc"));
+    assert_eq!(track.artist().as_deref(), Some("Some Artist"));
+    assert_eq!(track.genre().as_deref(), Some("House"));
+    assert_eq!(track.bpm(), Some(128.0));
+    assert_eq!(
+        track.duration().map(|duration| duration.as_millis()),
+        Some(123_456)
+    );
+    assert_eq!(track.comment().as_deref(), Some("nice one"));
+    assert_eq!(track.track_page_url(), format!("{url}/track/abc/"));
+    let playback_url = track.playback_url().expect("no playback URL");
+    assert!(playback_url.starts_with(&format!("{url}/api/track/abc/play.ogg?")));
+    assert!(playback_url.ends_with(&format!("X-Cular-Session={SESSION}")));
+}
+
+#[tokio::test]
+async fn track_dispatcher_tolerates_missing_details() {
+    let url = setup().await;
+
+    let tracks = dispatch(
+        &AudiotoolTrackRefDispatcher {},
+        &format!("{url}/track/missing/"),
+        None,
+    )
+    .await
+    .expect("track URL not recognized");
+
+    assert_eq!(tracks.len(), 1);
+    assert_eq!(tracks[0].title(), None);
+    assert_eq!(tracks[0].track_page_url(), format!("{url}/track/missing/"));
+}
+
+#[tokio::test]
+async fn dispatchers_ignore_foreign_references() {
+    setup().await;
+
+    let track_ref = "https://example.com/track/abc/";
+    assert!(dispatch(&AudiotoolTrackRefDispatcher {}, track_ref, None)
+        .await
+        .is_none());
+    assert!(
+        dispatch(&AudiotoolSingleChartsDispatcher {}, track_ref, None)
+            .await
+            .is_none()
+    );
+    assert!(
+        dispatch(&AudiotoolGenreChartsDispatcher {}, track_ref, None)
+            .await
+            .is_none()
+    );
+    assert!(dispatch(&AudiotoolAlbumDispatcher {}, track_ref, None)
+        .await
+        .is_none());
+}
+
+#[tokio::test]
+async fn single_charts_are_enqueued_in_reverse() {
+    setup().await;
+
+    let tracks = dispatch(
+        &AudiotoolSingleChartsDispatcher {},
+        "at:single-charts",
+        None,
+    )
+    .await
+    .expect("single charts not recognized");
+
+    assert_eq!(
+        comments(&tracks),
+        [
+            "#3 in Single Charts",
+            "#2 in Single Charts",
+            "#1 in Single Charts"
+        ]
+    );
+    assert_eq!(tracks[0].title().as_deref(), Some("Track chart-3"));
+}
+
+#[tokio::test]
+async fn genre_charts_are_enqueued_in_reverse() {
+    let url = setup().await;
+
+    let tracks = dispatch(
+        &AudiotoolGenreChartsDispatcher {},
+        &format!("{url}/genre/trap/charts/2021-35"),
+        None,
+    )
+    .await
+    .expect("genre charts not recognized");
+
+    assert_eq!(
+        comments(&tracks),
+        [
+            "#3 in trap top tracks",
+            "#2 in trap top tracks",
+            "#1 in trap top tracks"
+        ]
+    );
+    assert_eq!(tracks[2].title().as_deref(), Some("Track trap-1"));
+}
+
+#[tokio::test]
+async fn albums_are_enqueued_in_order() {
+    let url = setup().await;
+
+    let tracks = dispatch(
+        &AudiotoolAlbumDispatcher {},
+        &format!("{url}/album/xyz/"),
+        Some("album night"),
+    )
+    .await
+    .expect("album not recognized");
+
+    assert_eq!(comments(&tracks), ["album night"; 3]);
+    let titles: Vec<_> = tracks.iter().map(|track| track.title()).collect();
+    assert_eq!(
+        titles,
+        [
+            Some("Track xyz-1".to_owned()),
+            Some("Track xyz-2".to_owned()),
+            Some("Track xyz-3".to_owned())
+        ]
+    );
+}
+
+#[tokio::test]
+async fn album_tracks_are_resolved_concurrently_in_order() {
+    let url = setup().await;
+
+    let started = Instant::now();
+    let tracks = dispatch(
+        &AudiotoolAlbumDispatcher {},
+        &format!("{url}/album/slow/"),
+        None,
+    )
+    .await
+    .expect("album not recognized");
+    let elapsed = started.elapsed();
+
+    let titles: Vec<_> = tracks.iter().map(|track| track.title()).collect();
+    assert_eq!(
+        titles,
+        [
+            Some("Track slow-1".to_owned()),
+            Some("Track slow-2".to_owned()),
+            Some("Track slow-3".to_owned())
+        ]
+    );
+    // resolving one after another would take 600ms
+    assert!(elapsed < Duration::from_millis(550), "took {:?}", elapsed);
+}
+
+#[tokio::test]
+async fn private_tracks_are_resolved_after_logging_in() {
+    let url = setup().await;
+
+    let status = AUDIOTOOL_HTTP_CLIENT.status();
+    assert_eq!(status.username.as_deref(), Some(USERNAME));
+    assert!(
+        matches!(status.auth, AuthState::LoggedIn(_)),
+        "not logged in: {:?}",
+        status.auth
+    );
+
+    let tracks = dispatch(
+        &AudiotoolTrackRefDispatcher {},
+        &format!("{url}/track/private-abc/"),
+        None,
+    )
+    .await
+    .expect("track URL not recognized");
+
+    assert_eq!(tracks[0].title().as_deref(), Some("Track private-abc"));
+    assert!(tracks[0]
+        .playback_url()
+        .expect("no playback URL")
+        .ends_with(&format!("X-Cular-Session={SESSION}")));
+}
+
+#[tokio::test]
+async fn denied_tracks_are_reported() {
+    let url = setup().await;
+
+    let mut tracks = AudiotoolTrackRefDispatcher {}
+        .dispatch(
+            &format!("{url}/track/forbidden-abc/"),
+            None,
+            &User::default(),
+        )
+        .await
+        .expect("track URL not recognized");
+
+    match tracks.next().await {
+        Some(Err(err)) => assert!(err.contains("isn't allowed"), "unexpected error: {}", err),
+        Some(Ok(_)) => panic!("denied track has been resolved"),
+        None => panic!("no track has been resolved"),
+    }
+    // the session is still fine; the account just isn't allowed to access the track
+    let status = AUDIOTOOL_HTTP_CLIENT.status();
+    assert!(
+        matches!(status.auth, AuthState::LoggedIn(_)),
+        "session expired: {:?}",
+        status.auth
+    );
+}
+
+#[tokio::test]
+async fn rejected_credentials_are_not_tried_again() {
+    mock_url();
+    let client = AudiotoolHttpClient::from_env().with_credentials(USERNAME, "wrong-password");
+
+    client.keep_alive().await;
+    let status = client.status();
+    assert!(
+        matches!(status.auth, AuthState::Rejected(_)),
+        "credentials not rejected: {:?}",
+        status.auth
+    );
+    assert_eq!(REJECTED_LOGINS.load(Ordering::SeqCst), 1);
+
+    client.keep_alive().await;
+    assert_eq!(REJECTED_LOGINS.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn track_stats_are_resolved() {
+    let url = setup().await;
+
+    let tracks = dispatch(
+        &AudiotoolTrackRefDispatcher {},
+        &format!("{url}/track/remix-of-orig/"),
+        None,
+    )
+    .await
+    .expect("track URL not recognized");
+
+    let track = &tracks[0];
+    assert_eq!(track.artist_url(), Some(format!("{url}/user/artist/")));
+    let stats = track.stats().expect("track has no stats");
+    assert_eq!(
+        (stats.plays, stats.favourites, stats.comments),
+        (Some(1234), Some(56), Some(2))
+    );
+    let remix_of = track.remix_of().expect("track is no remix");
+    assert_eq!(remix_of.title.as_deref(), Some("Track orig"));
+    assert_eq!(remix_of.artist.as_deref(), Some("Original Artist"));
+    assert_eq!(remix_of.track_page_url, format!("{url}/track/orig/"));
+
+    let comments = track
+        .latest_comments(3)
+        .expect("track doesn't support comments")
+        .await
+        .unwrap_or_else(|err| panic!("failed to fetch comments: {}", err));
+    let comments: Vec<_> = comments
+        .iter()
+        .map(|comment| (comment.author.as_deref(), comment.text.as_str()))
+        .collect();
+    assert_eq!(
+        comments,
+        [(Some("Fan"), "love it"), (Some("Critic"), "too loud")]
+    );
+}
+
+#[tokio::test]
+async fn server_errors_are_retried_and_open_the_circuit() {
+    mock_url();
+    let client = AudiotoolHttpClient::from_env().with_policy(RequestPolicy {
+        max_retries: 2,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(1),
+        failure_threshold: 2,
+        open_duration: Duration::from_millis(200),
+        ..RequestPolicy::default()
+    });
+
+    assert!(client.request_track_details("broken-1").await.is_err());
+    assert_eq!(BROKEN_REQUESTS.load(Ordering::SeqCst), 3);
+    assert_eq!(client.status().consecutive_failures, 1);
+
+    // the server is fine, it just doesn't know the track
+    assert!(client.request_track_details("missing").await.is_err());
+    assert_eq!(client.status().consecutive_failures, 1);
+    assert_eq!(client.status().state, CircuitState::Closed);
+
+    assert!(client.request_track_details("broken-2").await.is_err());
+    assert_eq!(BROKEN_REQUESTS.load(Ordering::SeqCst), 6);
+    assert!(matches!(client.status().state, CircuitState::Open(_)));
+
+    // requests are rejected without bothering the server
+    assert!(client.request_track_details("broken-3").await.is_err());
+    assert_eq!(BROKEN_REQUESTS.load(Ordering::SeqCst), 6);
+
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert_eq!(client.status().state, CircuitState::HalfOpen);
+
+    // only a single probe may be in flight
+    let (probe, other) = tokio::join!(
+        client.request_track_details("slow-1"),
+        client.request_track_details("abc"),
+    );
+    assert!(probe.is_ok());
+    assert!(other.is_err());
+    assert_eq!(client.status().state, CircuitState::Closed);
+    assert_eq!(client.status().consecutive_failures, 0);
+}
@@ -1,9 +1,10 @@
 use std::time::Duration;
 
+use futures::{stream, StreamExt};
 use serenity::{model::prelude::User, prelude::TypeMapKey};
 use songbird::{create_player, input::Restartable};
 
-use super::{Track, TrackRefDispatcher};
+use super::{Track, TrackRefDispatcher, TrackStream};
 
 pub struct TrackKey {}
 
@@ -93,17 +94,14 @@ impl TrackRefDispatcher for YoutubeTrackRefDispatcher {
         track_ref: &str,
         comment: Option<String>,
         user: &User,
-    ) -> Option<Vec<Result<Box<dyn Track>, String>>> {
+    ) -> Option<TrackStream> {
         // TODO check for the URL being youtube at all
 
-        let mut results = Vec::new();
         let track = YoutubeTrack::try_from_url(track_ref, comment, user)
             .await
             .map(|track| Box::new(track) as Box<dyn Track>);
 
-        results.push(track);
-
-        Some(results)
+        Some(stream::once(async { track }).boxed())
     }
 }
 
@@ -113,8 +111,8 @@ impl Track for YoutubeTrack {
         &self.track_page_url
     }
 
-    fn playback_url(&self) -> &str {
-        &self.track_playback_url
+    fn playback_url(&self) -> Result<String, String> {
+        Ok(self.track_playback_url.clone())
     }
 
     fn duration(&self) -> Option<Duration> {